@@ -0,0 +1,25 @@
+#!/bin/bash
+set -e
+
+# containerd doesn't read hooks.d, the hook is added to the base OCI spec of
+# the runc runtime instead.
+BASE_SPEC="/etc/containerd/tetragon-base-spec.json"
+# Built with `cargo build --release --bin tetragon-rthooks`
+HOOK_BIN="${HOOK_BIN:-../../target/release/tetragon-rthooks}"
+
+# Create a directory to store the hook binary:
+mkdir -p /opt/oci-hook
+
+# Copy the hook binary to the directory:
+install -m 0755 "$HOOK_BIN" /opt/oci-hook/tetragon-rthooks
+
+# Generate the base spec with the createRuntime and poststop hooks:
+ctr oci spec \
+    | jq '.hooks.createRuntime = [{"path": "/opt/oci-hook/tetragon-rthooks", "args": ["tetragon-rthooks", "createRuntime"]}]
+        | .hooks.poststop = [{"path": "/opt/oci-hook/tetragon-rthooks", "args": ["tetragon-rthooks", "poststop"]}]' \
+    > "$BASE_SPEC"
+
+echo "OCI hook installed successfully."
+ls -la /opt/oci-hook/tetragon-rthooks
+ls -la "$BASE_SPEC"
+echo "Set base_runtime_spec = \"$BASE_SPEC\" for the runc runtime in /etc/containerd/config.toml and restart containerd."
@@ -1,18 +1,18 @@
 #!/bin/bash
 set -e
 
-# Create a directory to store the hook script:
-mkdir -p /opt/oci-hook
+# Built with `cargo build --release --bin tetragon-rthooks`
+HOOK_BIN="${HOOK_BIN:-../../target/release/tetragon-rthooks}"
 
-# Copy the hook script to the directory:
-cp ./oci-hook.sh /opt/oci-hook/
+# Create a directory to store the hook binary:
+mkdir -p /opt/oci-hook
 
-# Make the script executable:
-chmod +x /opt/oci-hook/oci-hook.sh
+# Copy the hook binary to the directory:
+install -m 0755 "$HOOK_BIN" /opt/oci-hook/tetragon-rthooks
 
-# Congfigure the hook script to be used by the OCI runtime:
-cp ./oci-hook.json /usr/share/containers/oci/hooks.d/
+# Congfigure the hooks to be used by the OCI runtime:
+cp ./oci-hook.json ./oci-hook-poststop.json /usr/share/containers/oci/hooks.d/
 
 echo "OCI hook installed successfully."
-ls -la /opt/oci-hook/oci-hook.sh
-ls -la /usr/share/containers/oci/hooks.d/oci-hook.json
+ls -la /opt/oci-hook/tetragon-rthooks
+ls -la /usr/share/containers/oci/hooks.d/oci-hook.json /usr/share/containers/oci/hooks.d/oci-hook-poststop.json
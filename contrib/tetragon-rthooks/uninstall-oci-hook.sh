@@ -1,6 +1,6 @@
 #!/bin/bash
 
 rm -f /opt/oci-hook/*
-rm /usr/share/containers/oci/hooks.d/oci-hook.json
+rm -f /usr/share/containers/oci/hooks.d/oci-hook.json /usr/share/containers/oci/hooks.d/oci-hook-poststop.json
 
 echo "OCI hook uninstalled successfully."
@@ -0,0 +1,22 @@
+use crate::vmlinux::*;
+
+// f_type of statfs() on cgroup filesystems
+pub const CGROUP_SUPER_MAGIC: __u64 = 0x27e0eb;
+pub const CGROUP2_SUPER_MAGIC: __u64 = 0x63677270;
+
+// Runtime configuration written by userspace at startup
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TetragonConf {
+    // Index of the cgroup v1 controller used to find the cgroup of tasks
+    pub tg_cgrpv1_subsys_idx: __u32,
+    // Set when ENVS_ALLOWLIST is, the environment of the processes is only
+    // sent as data events then
+    pub env_vars_enabled: __u32,
+    // CGROUP2_SUPER_MAGIC on unified hierarchies, CGROUP_SUPER_MAGIC on
+    // legacy and hybrid ones. 0 until detected, which is handled as cgroup v2.
+    pub cgrp_fs_magic: __u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TetragonConf {}
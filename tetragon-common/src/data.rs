@@ -0,0 +1,60 @@
+use core::mem;
+
+use crate::common::MsgCommon;
+use crate::vmlinux::*;
+
+#[repr(C)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DataEventId {
+    pub pid: __u64,
+    pub time: __u64,
+}
+
+#[repr(C)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DataEventDesc {
+    pub error: __s32,
+    pub pad: __u32,
+    pub leftover: __u32,
+    pub size: __u32,
+    pub id: DataEventId,
+}
+
+impl DataEventDesc {
+    // The desc is written in place of the data it describes, e.g. HeapExe.args
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < mem::size_of::<DataEventDesc>() {
+            return None;
+        }
+
+        unsafe {
+            let ptr = bytes.as_ptr() as *const DataEventDesc;
+            Some(ptr.read_unaligned())
+        }
+    }
+}
+
+pub const MSG_DATA_ARG_LEN: usize = 32736;
+// Size of MsgData without arg, data follows right after it
+pub const MSG_DATA_HEADER_LEN: usize = mem::size_of::<MsgCommon>() + mem::size_of::<DataEventId>();
+
+#[repr(C)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct MsgData {
+    pub common: MsgCommon,
+    pub id: DataEventId,
+    pub arg: [u8; MSG_DATA_ARG_LEN],
+}
+
+impl Default for MsgData {
+    fn default() -> Self {
+        Self {
+            common: MsgCommon::default(),
+            id: DataEventId::default(),
+            arg: [0; MSG_DATA_ARG_LEN],
+        }
+    }
+}
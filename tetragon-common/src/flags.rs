@@ -29,6 +29,22 @@ pub mod msg_flags {
     pub const EVENT_ERROR_PATH_COMPONENTS: __u64 = 0x400000;
     pub const EVENT_DATA_FILENAME: __u64 = 0x800000;
     pub const EVENT_DATA_ARGS: __u64 = 0x1000000;
+    pub const EVENT_DATA_ENVS: __u64 = 0x2000000;
 
     pub const EVENT_COMMON_FLAG_CLONE: __u64 = 0x01;
 }
+
+// Secure exec flags, reported in MsgProcess.secureexec
+#[allow(dead_code)]
+pub mod secureexec_flags {
+    use crate::vmlinux::__u32;
+
+    pub const EXECVE_SETUID: __u32 = 0x01;
+    pub const EXECVE_SETGID: __u32 = 0x02;
+    // Execution of a binary with file capabilities
+    pub const EXECVE_FILE_CAPS: __u32 = 0x04;
+    // Execution of a set-user-ID to root binary
+    pub const EXECVE_SETUID_ROOT: __u32 = 0x08;
+    // Execution of a set-group-ID to root binary
+    pub const EXECVE_SETGID_ROOT: __u32 = 0x10;
+}
@@ -1,9 +1,12 @@
 #![no_std]
 pub mod bpf_cred;
 pub mod common;
+pub mod conf;
+pub mod data;
 pub mod flags;
 pub mod msg_types;
 pub mod process;
+pub mod rate;
 #[allow(non_upper_case_globals)]
 #[allow(non_snake_case)]
 #[allow(non_camel_case_types)]
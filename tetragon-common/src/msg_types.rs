@@ -14,6 +14,7 @@ pub enum MsgOps {
     MsgOpData = 24,
     MsgOpCgroup = 25,
     MsgOpLoader = 26,
+    MsgOpThrottle = 27,
 }
 
 impl From<u8> for MsgOps {
@@ -29,6 +30,7 @@ impl From<u8> for MsgOps {
             24 => MsgOps::MsgOpData,
             25 => MsgOps::MsgOpCgroup,
             26 => MsgOps::MsgOpLoader,
+            27 => MsgOps::MsgOpThrottle,
             _ => MsgOps::MsgOpUndef,
         }
     }
@@ -3,12 +3,15 @@ use core::mem;
 
 use crate::bpf_cred::{MsgCapabilities, MsgCred};
 use crate::common::{MsgCommon, EVENT_SIZE};
+use crate::data::DataEventDesc;
 use crate::vmlinux::*;
 
 // In Linux, it's 4096, but simplified to 256 for easier debugging.
 pub const BINARY_PATH_MAX_LEN: usize = 256;
 // Usually 2MiB in most kernels, but simplified to 512 for easier debugging.
 pub const ARGS_MAX_LEN: usize = 512;
+// In Linux, it's 4096 (PATH_MAX), but simplified to 256 like BINARY_PATH_MAX_LEN.
+pub const CWD_MAX_LEN: usize = 256;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default)]
@@ -79,6 +82,8 @@ pub struct MsgK8s {
     pub net_ns: __u32,
     pub cid: __u32,
     pub cgrpid: __u64,
+    // Cgroup id of the tracked ancestor (e.g. the container cgroup), 0 if none
+    pub cgrp_tracker_id: __u64,
     pub docker_id: [u8; DOCKER_ID_LENGTH],
 }
 
@@ -88,6 +93,7 @@ impl Default for MsgK8s {
             net_ns: __u32::default(),
             cid: __u32::default(),
             cgrpid: __u64::default(),
+            cgrp_tracker_id: __u64::default(),
             docker_id: [0; DOCKER_ID_LENGTH],
         }
     }
@@ -98,6 +104,9 @@ impl Default for MsgK8s {
 pub struct HeapExe {
     pub filename: [u8; BINARY_PATH_MAX_LEN],
     pub args: [u8; ARGS_MAX_LEN],
+    pub cwd: [u8; CWD_MAX_LEN],
+    // Environment variables are always sent as MsgOpData events
+    pub envs: DataEventDesc,
     pub off: u8,
     pub len: __u32,
     pub error: __u32,
@@ -108,6 +117,8 @@ impl Default for HeapExe {
         Self {
             filename: [0; BINARY_PATH_MAX_LEN],
             args: [0; ARGS_MAX_LEN],
+            cwd: [0; CWD_MAX_LEN],
+            envs: DataEventDesc::default(),
             off: u8::default(),
             len: __u32::default(),
             error: __u32::default(),
@@ -238,6 +249,24 @@ pub struct ExitInfo {
     pub tid: __u32, // Thread ID
 }
 
+pub const MAX_IMA_HASH_SIZE: usize = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MsgImaHash {
+    pub algo: __s32, // enum hash_algo, 0 when the hash is not available
+    pub value: [u8; MAX_IMA_HASH_SIZE],
+}
+
+impl Default for MsgImaHash {
+    fn default() -> Self {
+        Self {
+            algo: __s32::default(),
+            value: [0; MAX_IMA_HASH_SIZE],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct MsgExecveEvent {
@@ -250,6 +279,7 @@ pub struct MsgExecveEvent {
     pub cleanup_key: MsgExecveKey,
     pub process: MsgProcess,
     pub exe: HeapExe,
+    pub ima: MsgImaHash,
 }
 
 impl TryFrom<[u8; EVENT_SIZE]> for MsgExecveEvent {
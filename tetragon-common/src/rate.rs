@@ -0,0 +1,100 @@
+use core::mem;
+
+use crate::common::{MsgCommon, EVENT_SIZE};
+use crate::process::MsgK8s;
+use crate::vmlinux::*;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CgroupRateKey {
+    pub id: __u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CgroupRateKey {}
+
+// The time is split in interval windows. curr and prev are the event counts
+// in the current window, which starts at time, and in the previous one.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CgroupRateValue {
+    pub curr: __u64,
+    pub prev: __u64,
+    pub time: __u64,
+    // ktime when the cgroup got throttled, 0 if it's not throttled
+    pub throttled: __u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CgroupRateValue {}
+
+impl CgroupRateValue {
+    pub fn new(time: __u64, interval: __u64) -> Self {
+        Self {
+            curr: 0,
+            prev: 0,
+            time: (time / interval) * interval,
+            throttled: 0,
+        }
+    }
+
+    // Moves the windows forward so that time falls into the current one.
+    pub fn slide(&mut self, time: __u64, interval: __u64) {
+        let delta = time.saturating_sub(self.time);
+        if delta < interval {
+            return;
+        }
+
+        if delta >= 2 * interval {
+            self.prev = 0;
+            self.time = (time / interval) * interval;
+        } else {
+            self.prev = self.curr;
+            self.time += interval;
+        }
+        self.curr = 0;
+    }
+
+    // Events within the last interval: the current window plus the part of
+    // the previous one that still overlaps with the sliding window.
+    pub fn rate(&self, time: __u64, interval: __u64) -> __u64 {
+        let slide = interval.saturating_sub(time.saturating_sub(self.time));
+        (self.prev * slide) / interval + self.curr
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CgroupRateOptions {
+    // Max number of execve events per interval, per cgroup
+    pub events: __u64,
+    // Interval in nanoseconds, 0 disables the rate limit
+    pub interval: __u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CgroupRateOptions {}
+
+// Sent when a cgroup starts being throttled. The stop is detected and
+// reported by userspace.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MsgThrottle {
+    pub common: MsgCommon,
+    pub kube: MsgK8s,
+}
+
+impl TryFrom<[u8; EVENT_SIZE]> for MsgThrottle {
+    type Error = &'static str;
+
+    fn try_from(bytes: [u8; EVENT_SIZE]) -> Result<Self, Self::Error> {
+        if bytes.len() < mem::size_of::<MsgThrottle>() {
+            return Err("Byte array is too small for MsgThrottle");
+        }
+
+        unsafe {
+            let ptr = bytes.as_ptr() as *const MsgThrottle;
+            Ok(ptr.read_unaligned())
+        }
+    }
+}
@@ -1,34 +1,83 @@
 use aya_ebpf::helpers::{bpf_get_current_cgroup_id, bpf_probe_read_kernel};
+use tetragon_common::conf::CGROUP2_SUPER_MAGIC;
+use tetragon_common::flags::msg_flags::{
+    EVENT_ERROR_CGROUPS, EVENT_ERROR_CGROUP_SUBSYS, EVENT_ERROR_CGROUP_SUBSYSCGRP,
+};
 use tetragon_common::vmlinux::*;
 
+/**
+ * get_task_cgroup() Returns the cgroup of the task
+ * @task: target task
+ * @cgrpfs_ver: statfs() magic of the cgroup filesystem
+ * @subsys_idx: index of the cgroup v1 controller to use
+ * @error_flags: EVENT_ERROR_CGROUP_* flags set on failures
+ *
+ * On cgroup v2 this is the default hierarchy cgroup. On cgroup v1 and
+ * hybrid setups the cgroup of the controller at @subsys_idx is used.
+ */
 #[inline]
-#[allow(dead_code)]
 pub unsafe fn get_task_cgroup(
     task: *const task_struct,
-    _cgrpfs_ver: __u64,
-    _subsys_idx: __u32,
-    _error_flags: &mut __u32,
+    cgrpfs_ver: __u64,
+    subsys_idx: __u32,
+    error_flags: &mut __u32,
 ) -> Option<*const cgroup> {
-    // Now support only Cgroup v2
-
-    let cgroups_ptr: *const css_set = match bpf_probe_read_kernel(&(*task).cgroups) {
-        Ok(ptr) => ptr as *const css_set,
-        Err(_) => return None,
+    let cgroups: *const css_set = match bpf_probe_read_kernel(&(*task).cgroups) {
+        Ok(ptr) if !ptr.is_null() => ptr,
+        _ => {
+            *error_flags |= EVENT_ERROR_CGROUPS as __u32;
+            return None;
+        }
     };
 
-    let dfl_cgrp: *const cgroup = match bpf_probe_read_kernel(&(*cgroups_ptr).dfl_cgrp) {
-        Ok(ptr) => ptr as *const cgroup,
-        Err(_) => return None,
+    // 0 means the filesystem was not detected, default to cgroup v2
+    if cgrpfs_ver == CGROUP2_SUPER_MAGIC || cgrpfs_ver == 0 {
+        return match bpf_probe_read_kernel(&(*cgroups).dfl_cgrp) {
+            Ok(ptr) if !ptr.is_null() => Some(ptr),
+            _ => None,
+        };
+    }
+
+    // Cgroup v1, this check is also needed to pass verifier check
+    let subsys = &(*cgroups).subsys;
+    if subsys_idx as usize >= subsys.len() {
+        *error_flags |= EVENT_ERROR_CGROUP_SUBSYS as __u32;
+        return None;
+    }
+
+    let css: *const cgroup_subsys_state = match bpf_probe_read_kernel(&subsys[subsys_idx as usize])
+    {
+        Ok(ptr) if !ptr.is_null() => ptr,
+        _ => {
+            *error_flags |= EVENT_ERROR_CGROUP_SUBSYS as __u32;
+            return None;
+        }
     };
 
-    Some(dfl_cgrp)
+    match bpf_probe_read_kernel(&(*css).cgroup) {
+        Ok(ptr) if !ptr.is_null() => Some(ptr),
+        _ => {
+            *error_flags |= EVENT_ERROR_CGROUP_SUBSYSCGRP as __u32;
+            None
+        }
+    }
 }
 
+/**
+ * __tg_get_current_cgroup_id() Returns the cgroup id of the current task
+ * @cgrp: cgroup returned by get_task_cgroup()
+ * @cgrpfs_ver: statfs() magic of the cgroup filesystem
+ *
+ * bpf_get_current_cgroup_id() only works on the default hierarchy, so the
+ * id of @cgrp is read on cgroup v1. Returns zero on failures.
+ */
 #[inline]
-#[allow(dead_code)]
-pub unsafe fn __tg_get_current_cgroup_id(_cgrp: *const cgroup, _cgrpfs_ver: __u64) -> __u64 {
-    // Now support only Cgroup v2
-    bpf_get_current_cgroup_id()
+pub unsafe fn __tg_get_current_cgroup_id(cgrp: *const cgroup, cgrpfs_ver: __u64) -> __u64 {
+    if cgrpfs_ver == CGROUP2_SUPER_MAGIC || cgrpfs_ver == 0 {
+        bpf_get_current_cgroup_id()
+    } else {
+        get_cgroup_id(cgrp)
+    }
 }
 
 /**
@@ -52,3 +101,39 @@ pub unsafe fn get_cgroup_name(cgrp: *const cgroup) -> Option<*const u8> {
 
     Some(name_ptr)
 }
+
+/**
+ * get_cgroup_id() Returns the cgroup id
+ * @cgrp: target cgroup
+ *
+ * The cgroup id is the inode number of its kernfs node, the same value
+ * returned by bpf_get_current_cgroup_id() and name_to_handle_at(). Returns
+ * zero on failures.
+ */
+#[inline]
+pub unsafe fn get_cgroup_id(cgrp: *const cgroup) -> __u64 {
+    let Ok(kn) = bpf_probe_read_kernel(&(*cgrp).kn) else {
+        return 0;
+    };
+    bpf_probe_read_kernel(&(*kn).id).unwrap_or(0)
+}
+
+/**
+ * cgroup_get_parent_id() Returns the cgroup id of the parent
+ * @cgrp: target cgroup
+ *
+ * Returns zero for the root cgroup or on failures.
+ */
+#[inline]
+pub unsafe fn cgroup_get_parent_id(cgrp: *const cgroup) -> __u64 {
+    let Ok(kn) = bpf_probe_read_kernel(&(*cgrp).kn) else {
+        return 0;
+    };
+    let Ok(parent) = bpf_probe_read_kernel(&(*kn).parent) else {
+        return 0;
+    };
+    if parent.is_null() {
+        return 0;
+    }
+    bpf_probe_read_kernel(&(*parent).id).unwrap_or(0)
+}
@@ -1,42 +1,175 @@
-use tetragon_common::common::MsgCommon;
-use tetragon_common::vmlinux::*;
-
-#[repr(C)]
-#[repr(packed)]
-#[derive(Copy, Clone, Debug, Default)]
-pub struct DataEventId {
-    pub pid: __u64,
-    pub time: __u64,
+use crate::maps;
+use aya_ebpf::bindings::BPF_F_CURRENT_CPU;
+use aya_ebpf::helpers::gen::{
+    bpf_get_current_pid_tgid, bpf_ktime_get_ns, bpf_perf_event_output, bpf_probe_read_kernel_str,
+    bpf_probe_read_user,
+};
+use aya_ebpf::EbpfContext;
+use tetragon_common::data::{DataEventDesc, MsgData, MSG_DATA_ARG_LEN, MSG_DATA_HEADER_LEN};
+use tetragon_common::msg_types::MsgOps;
+
+// Max number of MsgData chunks sent for one data event. In Tetragon, it's 10
+// for large programs, but simplified to 4.
+const DATA_EVENT_LOOPS: usize = 4;
+
+// Returns the result of bpf_perf_event_output, negative when the chunk was
+// dropped, like when the perf buffer of the CPU is full.
+#[inline]
+unsafe fn data_event_output<C: EbpfContext>(ctx: &C, msg: &mut MsgData, bytes: usize) -> i64 {
+    msg.common.size = (MSG_DATA_HEADER_LEN + bytes) as u32;
+    // PerfEventArray::output always sends size_of::<T>(), so call the helper
+    // directly to send only the used part of arg.
+    bpf_perf_event_output(
+        ctx.as_ptr(),
+        &raw const maps::TCPMON_MAP as *mut aya_ebpf_cty::c_void,
+        BPF_F_CURRENT_CPU as u64,
+        msg as *mut MsgData as *mut aya_ebpf_cty::c_void,
+        (MSG_DATA_HEADER_LEN + bytes) as u64,
+    )
 }
 
-#[repr(C)]
-#[repr(packed)]
-#[derive(Copy, Clone, Debug, Default)]
-pub struct DataEventDesc {
-    pub error: __s32,
-    pub pad: __u32,
-    pub leftover: __u32,
-    pub size: __u32,
-    pub id: DataEventId,
+#[inline]
+unsafe fn __do_bytes<C: EbpfContext>(
+    ctx: &C,
+    msg: &mut MsgData,
+    uptr: u64,
+    bytes: usize,
+) -> Result<usize, i64> {
+    // This is needed to pass verifier check
+    let bytes = bytes.min(MSG_DATA_ARG_LEN);
+
+    let err = bpf_probe_read_user(
+        msg.arg.as_mut_ptr() as *mut aya_ebpf_cty::c_void,
+        bytes as u32,
+        uptr as *const aya_ebpf_cty::c_void,
+    );
+    if err < 0 {
+        return Err(err);
+    }
+
+    let err = data_event_output(ctx, msg, bytes);
+    if err < 0 {
+        return Err(err);
+    }
+    Ok(bytes)
 }
 
-const MSG_DATA_ARG_LEN: usize = 32736;
+#[inline]
+unsafe fn do_bytes<C: EbpfContext>(
+    ctx: &C,
+    msg: &mut MsgData,
+    uptr: u64,
+    bytes: usize,
+) -> Result<usize, i64> {
+    let mut rd_bytes = 0;
 
-#[repr(C)]
-#[repr(packed)]
-#[derive(Copy, Clone, Debug)]
-pub struct MsgData {
-    pub common: MsgCommon,
-    pub id: DataEventId,
-    pub arg: [u8; MSG_DATA_ARG_LEN],
+    for _ in 0..DATA_EVENT_LOOPS {
+        rd_bytes += __do_bytes(ctx, msg, uptr + rd_bytes as u64, bytes - rd_bytes)?;
+        if rd_bytes == bytes {
+            break;
+        }
+    }
+
+    // leftover is reported by the caller
+    Ok(rd_bytes)
 }
 
-impl Default for MsgData {
-    fn default() -> Self {
-        Self {
-            common: MsgCommon::default(),
-            id: DataEventId::default(),
-            arg: [0; MSG_DATA_ARG_LEN],
+#[inline]
+unsafe fn msg_data_init() -> Option<&'static mut MsgData> {
+    let msg = &mut *maps::DATA_HEAP.get_ptr_mut(0)?;
+
+    msg.common.op = MsgOps::MsgOpData as u8;
+    msg.common.flags = 0;
+    msg.common.pad = [0; 2];
+    msg.common.ktime = bpf_ktime_get_ns();
+
+    msg.id.pid = bpf_get_current_pid_tgid();
+    msg.id.time = msg.common.ktime;
+
+    Some(msg)
+}
+
+#[inline]
+fn data_event_desc_set(desc: &mut DataEventDesc, res: Result<usize, i64>, size: usize) {
+    desc.pad = 0;
+    match res {
+        Ok(bytes) => {
+            desc.error = 0;
+            desc.leftover = (size - bytes) as u32;
+            desc.size = bytes as u32;
+        }
+        Err(err) => {
+            desc.error = err as i32;
+            desc.leftover = 0;
+            desc.size = 0;
         }
     }
 }
+
+/**
+ * data_event_bytes() Sends user memory as MsgData events
+ * @ctx: program context
+ * @desc: descriptor written in place of the data in the original event
+ * @uptr: user pointer to the data
+ * @size: size of the data
+ *
+ * The data is split into MSG_DATA_ARG_LEN chunks which share the same
+ * DataEventId, so the observer can reassemble them before it decodes the
+ * event that carries @desc. Anything beyond DATA_EVENT_LOOPS chunks is
+ * reported in desc->leftover.
+ */
+#[inline]
+pub unsafe fn data_event_bytes<C: EbpfContext>(
+    ctx: &C,
+    desc: &mut DataEventDesc,
+    uptr: u64,
+    size: usize,
+) -> Result<(), i64> {
+    let msg = msg_data_init().ok_or(0)?;
+    desc.id = msg.id;
+
+    let res = do_bytes(ctx, msg, uptr, size);
+    data_event_desc_set(desc, res, size);
+    res.map(|_| ())
+}
+
+/**
+ * data_event_str() Sends a kernel string as a single MsgData event
+ * @ctx: program context
+ * @desc: descriptor written in place of the data in the original event
+ * @kptr: kernel pointer to the NUL terminated string
+ *
+ * Used for strings bounded by PATH_MAX like linux_binprm->filename, which
+ * always fit into one chunk. The terminating NUL is not sent.
+ */
+#[inline]
+pub unsafe fn data_event_str<C: EbpfContext>(
+    ctx: &C,
+    desc: &mut DataEventDesc,
+    kptr: *const u8,
+) -> Result<(), i64> {
+    let msg = msg_data_init().ok_or(0)?;
+    desc.id = msg.id;
+
+    let ret = bpf_probe_read_kernel_str(
+        msg.arg.as_mut_ptr() as *mut aya_ebpf_cty::c_void,
+        MSG_DATA_ARG_LEN as u32,
+        kptr as *const aya_ebpf_cty::c_void,
+    );
+    let res = if ret < 1 {
+        Err(ret)
+    } else {
+        // This mask is needed to pass verifier check
+        let bytes = (ret as usize - 1) & 0x7fff;
+        let err = data_event_output(ctx, msg, bytes);
+        if err < 0 {
+            Err(err)
+        } else {
+            Ok(bytes)
+        }
+    };
+
+    let size = *res.as_ref().unwrap_or(&0);
+    data_event_desc_set(desc, res, size);
+    res.map(|_| ())
+}
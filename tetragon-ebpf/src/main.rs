@@ -2,17 +2,25 @@
 #![no_main]
 
 mod lib_bpf_cgroup;
+#[allow(static_mut_refs)]
 mod lib_data_msg;
 mod lib_helper;
 #[allow(static_mut_refs)]
 mod lib_process;
 mod maps;
 #[allow(static_mut_refs)]
+mod process_bpf_cgtracker;
+#[allow(static_mut_refs)]
+mod process_bpf_execve_bprm_commit_creds;
+#[allow(static_mut_refs)]
 mod process_bpf_execve_event;
 #[allow(static_mut_refs)]
+mod process_bpf_execve_ima;
+#[allow(static_mut_refs)]
 mod process_bpf_exit;
 #[allow(static_mut_refs)]
 mod process_bpf_fork;
+#[allow(static_mut_refs)]
 mod process_bpf_process_event;
 mod process_bpf_rate;
 mod process_bpf_task;
@@ -1,9 +1,12 @@
 use aya_ebpf::{
     macros::map,
-    maps::{HashMap, LruHashMap, PerCpuArray, PerfEventArray, ProgramArray},
+    maps::{Array, HashMap, LruHashMap, PerCpuArray, PerfEventArray, ProgramArray},
 };
 
-use tetragon_common::process::{EventBytes, ExecveInfo, ExecveMapValue, KernelStats};
+use tetragon_common::conf::TetragonConf;
+use tetragon_common::data::MsgData;
+use tetragon_common::process::{EventBytes, ExecveInfo, ExecveMapValue, KernelStats, MsgImaHash};
+use tetragon_common::rate::{CgroupRateKey, CgroupRateOptions, CgroupRateValue};
 use tetragon_common::vmlinux::{__u32, __u64};
 
 #[map(name = "EXECVE_MAP")]
@@ -19,6 +22,9 @@ pub struct Heap {
 #[map(name = "GARBAGE_HEAP")]
 pub static mut GARBAGE_HEAP: PerCpuArray<Heap> = PerCpuArray::with_max_entries(1, 0);
 
+#[map(name = "DATA_HEAP")]
+pub static mut DATA_HEAP: PerCpuArray<MsgData> = PerCpuArray::with_max_entries(1, 0);
+
 #[map(name = "TCPMON_MAP")]
 pub static TCPMON_MAP: PerfEventArray<EventBytes> = PerfEventArray::new(0);
 
@@ -39,6 +45,34 @@ pub static mut EXIT_HEAP_MAP: PerCpuArray<EventBytes> = PerCpuArray::with_max_en
 pub static mut TG_EXECVE_JOINED_INFO_MAP: LruHashMap<__u64, ExecveInfo> =
     LruHashMap::with_max_entries(8192, 0);
 
+// Keyed by pid_tgid of the task running execve
+#[map(name = "TG_IMA_HASH_MAP")]
+pub static mut TG_IMA_HASH_MAP: LruHashMap<__u64, MsgImaHash> =
+    LruHashMap::with_max_entries(8192, 0);
+
+#[map(name = "CGROUP_RATE_MAP")]
+pub static mut CGROUP_RATE_MAP: LruHashMap<CgroupRateKey, CgroupRateValue> =
+    LruHashMap::with_max_entries(32768, 0);
+
+// Throttles of CGROUP_RATE_MAP that userspace saw end, keyed like it with the
+// throttled time. Cleared on the next execve of the cgroup, so that userspace
+// never overwrites the counts.
+#[map(name = "CGROUP_RATE_CLEAR_MAP")]
+pub static mut CGROUP_RATE_CLEAR_MAP: LruHashMap<CgroupRateKey, __u64> =
+    LruHashMap::with_max_entries(32768, 0);
+
+// Written by userspace, the rate limit is disabled while interval is 0
+#[map(name = "CGROUP_RATE_OPTIONS_MAP")]
+pub static CGROUP_RATE_OPTIONS_MAP: Array<CgroupRateOptions> = Array::with_max_entries(1, 0);
+
+// Maps the id of a cgroup to the id of the tracked cgroup it's nested in.
+// Entries for the tracked cgroups themselves are added by userspace.
+#[map(name = "TG_CGTRACKER_MAP")]
+pub static mut TG_CGTRACKER_MAP: HashMap<__u64, __u64> = HashMap::with_max_entries(16384, 0);
+
+#[map(name = "TG_CONF_MAP")]
+pub static TG_CONF_MAP: Array<TetragonConf> = Array::with_max_entries(1, 0);
+
 #[map(name = "TG_STATS_MAP")]
 pub static mut TG_STATS_MAP: PerCpuArray<KernelStats> = PerCpuArray::with_max_entries(1, 0);
 
@@ -0,0 +1,50 @@
+use crate::lib_bpf_cgroup::{cgroup_get_parent_id, get_cgroup_id};
+use crate::maps;
+use aya_ebpf::{macros::btf_tracepoint, programs::BtfTracePointContext};
+use tetragon_common::vmlinux::{__u64, cgroup};
+
+// Cgroups created under a tracked cgroup are tracked by the same cgroup, so
+// processes in nested cgroups (e.g. systemd in a container or the crio
+// conmon sub-cgroups) are resolved to their container.
+#[btf_tracepoint(function = "cgroup_mkdir")]
+pub fn tg_cgtracker_cgroup_mkdir(ctx: BtfTracePointContext) -> u32 {
+    unsafe { try_cgtracker_cgroup_mkdir(ctx) };
+    0
+}
+
+unsafe fn try_cgtracker_cgroup_mkdir(ctx: BtfTracePointContext) {
+    let cgrp: *const cgroup = ctx.arg(0);
+
+    let cgid = get_cgroup_id(cgrp);
+    if cgid == 0 {
+        return;
+    }
+    let cgid_parent = cgroup_get_parent_id(cgrp);
+    if cgid_parent == 0 {
+        return;
+    }
+
+    if let Some(&tracker_id) = maps::TG_CGTRACKER_MAP.get(&cgid_parent) {
+        let _ = maps::TG_CGTRACKER_MAP.insert(&cgid, &tracker_id, 0);
+    }
+}
+
+#[btf_tracepoint(function = "cgroup_rmdir")]
+pub fn tg_cgtracker_cgroup_rmdir(ctx: BtfTracePointContext) -> u32 {
+    unsafe { try_cgtracker_cgroup_rmdir(ctx) };
+    0
+}
+
+unsafe fn try_cgtracker_cgroup_rmdir(ctx: BtfTracePointContext) {
+    let cgrp: *const cgroup = ctx.arg(0);
+
+    let cgid = get_cgroup_id(cgrp);
+    if cgid != 0 {
+        let _ = maps::TG_CGTRACKER_MAP.remove(&cgid);
+    }
+}
+
+#[inline]
+pub unsafe fn cgrp_get_tracker_id(cgid: __u64) -> __u64 {
+    maps::TG_CGTRACKER_MAP.get(&cgid).copied().unwrap_or(0)
+}
@@ -0,0 +1,95 @@
+use crate::maps;
+use aya_ebpf::helpers::{bpf_get_current_pid_tgid, bpf_get_current_task, bpf_probe_read_kernel};
+use aya_ebpf::{macros::kprobe, programs::ProbeContext};
+use tetragon_common::flags::secureexec_flags::*;
+use tetragon_common::process::ExecveInfo;
+use tetragon_common::vmlinux::{__u32, __u64, linux_binprm, task_struct};
+
+#[kprobe(function = "security_bprm_committing_creds")]
+pub fn tg_kp_bprm_committing_creds(ctx: ProbeContext) -> u32 {
+    match unsafe { try_tg_kp_bprm_committing_creds(ctx) } {
+        Ok(ret) => ret,
+        Err(ret) => ret.try_into().unwrap(),
+    }
+}
+
+#[inline]
+fn __cap_gained(new: __u64, old: __u64) -> bool {
+    new & !old != 0
+}
+
+#[inline]
+fn __is_uid_global_root(uid: __u32) -> bool {
+    uid == 0
+}
+
+/**
+ * Called with the new credentials of the execve already computed into
+ * bprm->cred. Compares them with the current credentials and stores the
+ * result into TG_EXECVE_JOINED_INFO_MAP, which is read by sched_process_exec.
+ *
+ * No probe is needed on security_bprm_creds_from_file(): begin_new_exec()
+ * calls bprm_creds_from_file(), which applies the set-user-ID bits and the
+ * file capabilities to bprm->cred, before security_bprm_committing_creds().
+ * This probe sees the elevation computed there, and also covers the kernels
+ * before 5.8 which compute it in security_bprm_set_creds() instead.
+ */
+unsafe fn try_tg_kp_bprm_committing_creds(ctx: ProbeContext) -> Result<u32, i64> {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let pid = (pid_tgid >> 32) as __u32;
+
+    // Only processes tracked in the execve map
+    if maps::EXECVE_MAP.get(&pid).is_none() {
+        return Ok(0);
+    }
+
+    let bprm: *const linux_binprm = ctx.arg(0).ok_or(1)?;
+    let mut info = ExecveInfo::default();
+
+    // Read inode information first, i_nlink == 0 means memfd or deleted binary
+    let file = bpf_probe_read_kernel(&(*bprm).file)?;
+    let inode = bpf_probe_read_kernel(&(*file).f_inode)?;
+    info.i_nlink = bpf_probe_read_kernel(&(*inode).__bindgen_anon_1.__i_nlink)?;
+    info.i_ino = bpf_probe_read_kernel(&(*inode).i_ino)?;
+
+    // Only the compared fields, a struct cred doesn't fit twice on the stack
+    let new_cred = bpf_probe_read_kernel(&(*bprm).cred)?;
+    let task = bpf_get_current_task() as *const task_struct;
+    let cred = bpf_probe_read_kernel(&(*task).cred)?;
+
+    let euid = bpf_probe_read_kernel(&(*new_cred).euid.val)?;
+    let egid = bpf_probe_read_kernel(&(*new_cred).egid.val)?;
+    let uid = bpf_probe_read_kernel(&(*cred).uid.val)?;
+    let gid = bpf_probe_read_kernel(&(*cred).gid.val)?;
+
+    let mut sec = 0;
+    if euid != uid {
+        sec |= EXECVE_SETUID;
+        if __is_uid_global_root(euid) {
+            sec |= EXECVE_SETUID_ROOT;
+        }
+    }
+    if egid != gid {
+        sec |= EXECVE_SETGID;
+        if __is_uid_global_root(egid) {
+            sec |= EXECVE_SETGID_ROOT;
+        }
+    }
+
+    // If ambient capabilities are set, then no file capabilities were applied.
+    // Privileges gained by set-user-ID to root are reported above.
+    if euid == uid && bpf_probe_read_kernel(&(*new_cred).cap_ambient.val)? == 0 {
+        let new_permitted = bpf_probe_read_kernel(&(*new_cred).cap_permitted.val)?;
+        let permitted = bpf_probe_read_kernel(&(*cred).cap_permitted.val)?;
+        if __cap_gained(new_permitted, permitted) {
+            sec |= EXECVE_FILE_CAPS;
+        }
+    }
+
+    if sec != 0 || info.i_nlink == 0 {
+        info.secureexec = sec;
+        maps::TG_EXECVE_JOINED_INFO_MAP.insert(&pid_tgid, &info, 0)?;
+    }
+
+    Ok(0)
+}
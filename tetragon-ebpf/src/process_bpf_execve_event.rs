@@ -1,8 +1,11 @@
+use crate::lib_data_msg::{data_event_bytes, data_event_str};
 use crate::lib_helper::offset_of;
 use crate::lib_process;
 use crate::maps;
+use crate::process_bpf_execve_ima::read_ima_hash;
 use crate::process_bpf_process_event::{
     __event_get_cgroup_info, event_set_clone, get_auid, get_current_subj_creds, get_namespaces,
+    getcwd,
 };
 use crate::process_bpf_rate::cgroup_rate;
 use crate::process_bpf_task::{event_find_parent, event_minimal_parent, get_task_pid_vnr};
@@ -14,8 +17,10 @@ use aya_ebpf::{
     helpers::{bpf_ktime_get_ns, bpf_probe_read_kernel},
     macros::btf_tracepoint,
     programs::BtfTracePointContext,
+    EbpfContext,
 };
 use aya_log_ebpf::*;
+use tetragon_common::data::{DataEventDesc, MSG_DATA_ARG_LEN};
 use tetragon_common::flags::msg_flags;
 use tetragon_common::msg_types::MsgOps;
 use tetragon_common::process::{
@@ -68,7 +73,7 @@ unsafe fn try_sched_process_exec(ctx: BtfTracePointContext) -> Result<u32, i64>
     event.process.size = offset_of::<MsgProcess>(|p| unsafe { &(*p).args as *const _ }) as u32;
     event.process.auid = get_auid() as u32;
 
-    read_execve_shared_info(&mut event.process, pid as u64);
+    read_execve_shared_info(&mut event.process, bpf_get_current_pid_tgid());
 
     event.common.op = MsgOps::MsgOpExecve as u8;
     event.common.ktime = event.process.ktime;
@@ -91,14 +96,16 @@ unsafe fn try_sched_process_exec(ctx: BtfTracePointContext) -> Result<u32, i64>
     let linux_binprm: *const linux_binprm = ctx.arg(2);
     let linux_binprm: &linux_binprm = &*linux_binprm;
     let filename_ptr = linux_binprm.filename;
-    bpf_probe_read_kernel_str_bytes(filename_ptr, &mut event.exe.filename)
-        .map(|s| core::str::from_utf8_unchecked(s))?;
+    read_filename(&ctx, filename_ptr, event);
 
-    let _ = read_args(task, event);
+    let _ = read_args(&ctx, task, event);
+    let _ = read_envs(&ctx, task, event);
 
-    __event_get_cgroup_info(task, &mut event.kube);
+    read_ima_hash(&mut event.ima);
 
-    // p->flags |= __event_get_cgroup_info(task, &event->kube);
+    event.process.flags |= getcwd(task, &mut event.exe.cwd);
+
+    event.process.flags |= __event_get_cgroup_info(task, &mut event.kube);
 
     let res = maps::EXECVE_CALLS.tail_call(&ctx, 0);
     if res.is_err() {
@@ -216,7 +223,35 @@ unsafe fn read_execve_shared_info(p: &mut MsgProcess, pid: __u64) {
 }
 
 #[inline]
-unsafe fn read_args(task: *const task_struct, event: &mut MsgExecveEvent) -> Result<u32, i64> {
+unsafe fn read_filename<C: EbpfContext>(ctx: &C, filename: *const u8, event: &mut MsgExecveEvent) {
+    match bpf_probe_read_kernel_str_bytes(filename, &mut event.exe.filename) {
+        // A filename of exactly BINARY_PATH_MAX_LEN - 1 may have been truncated
+        Ok(s) if s.len() < BINARY_PATH_MAX_LEN - 1 => return,
+        Ok(_) => {}
+        Err(_) => {
+            event.process.flags |= msg_flags::EVENT_ERROR_FILENAME as __u32;
+            return;
+        }
+    }
+
+    // Too long for exe.filename, send it as a data event and leave the desc in its place
+    let desc = &mut *(event.exe.filename.as_mut_ptr() as *mut DataEventDesc);
+    if data_event_str(ctx, desc, filename).is_err() {
+        event.process.flags |= msg_flags::EVENT_ERROR_FILENAME as __u32;
+        return;
+    }
+    event.process.flags |= msg_flags::EVENT_DATA_FILENAME as __u32;
+    if desc.size as usize >= MSG_DATA_ARG_LEN - 1 {
+        event.process.flags |= msg_flags::EVENT_TRUNC_FILENAME as __u32;
+    }
+}
+
+#[inline]
+unsafe fn read_args<C: EbpfContext>(
+    ctx: &C,
+    task: *const task_struct,
+    event: &mut MsgExecveEvent,
+) -> Result<u32, i64> {
     let mm: *mut mm_struct = bpf_probe_read_kernel(&(*task).mm)?;
     let arg_start = bpf_probe_read_kernel(&(*mm).__bindgen_anon_1.arg_start)?;
     let arg_end = bpf_probe_read_kernel(&(*mm).__bindgen_anon_1.arg_end)?;
@@ -230,9 +265,24 @@ unsafe fn read_args(task: *const task_struct, event: &mut MsgExecveEvent) -> Res
 
     let arg_start = arg_start + binary_path.len() as u64 + 1;
 
-    let args_len = (arg_end - arg_start) as u32;
+    let args_len = (arg_end - arg_start) as usize;
+    if args_len > ARGS_MAX_LEN {
+        // Too long for exe.args, send them as data events and leave the desc in their place
+        let desc = &mut *(event.exe.args.as_mut_ptr() as *mut DataEventDesc);
+        if data_event_bytes(ctx, desc, arg_start, args_len).is_err() {
+            event.process.flags |= msg_flags::EVENT_ERROR_ARGS as __u32;
+            return Ok(0);
+        }
+        event.process.flags |= msg_flags::EVENT_DATA_ARGS as __u32;
+        if desc.leftover != 0 {
+            event.process.flags |= msg_flags::EVENT_TRUNC_ARGS as __u32;
+        }
+        return Ok(0);
+    }
     // This is needed to pass verifier check
-    let args_len = args_len.min(ARGS_MAX_LEN as u32);
+    let args_len = (args_len as u32).min(ARGS_MAX_LEN as u32);
+    // Userspace needs the length to tell trailing empty args from the padding
+    event.exe.len = args_len;
 
     bpf_probe_read_user(
         event.exe.args.as_mut_ptr() as *mut aya_ebpf_cty::c_void,
@@ -241,3 +291,35 @@ unsafe fn read_args(task: *const task_struct, event: &mut MsgExecveEvent) -> Res
     );
     Ok(0)
 }
+
+#[inline]
+unsafe fn read_envs<C: EbpfContext>(
+    ctx: &C,
+    task: *const task_struct,
+    event: &mut MsgExecveEvent,
+) -> Result<u32, i64> {
+    // Off unless userspace has an allowlist, they would all be dropped
+    if !maps::TG_CONF_MAP
+        .get(0)
+        .is_some_and(|conf| conf.env_vars_enabled != 0)
+    {
+        return Ok(0);
+    }
+
+    let mm: *mut mm_struct = bpf_probe_read_kernel(&(*task).mm)?;
+    let env_start = bpf_probe_read_kernel(&(*mm).__bindgen_anon_1.env_start)?;
+    let env_end = bpf_probe_read_kernel(&(*mm).__bindgen_anon_1.env_end)?;
+    if env_end <= env_start {
+        return Ok(0);
+    }
+
+    // Envs are filtered in userspace, so send all of them as data events
+    data_event_bytes(
+        ctx,
+        &mut event.exe.envs,
+        env_start,
+        (env_end - env_start) as usize,
+    )?;
+    event.process.flags |= msg_flags::EVENT_DATA_ENVS as __u32;
+    Ok(0)
+}
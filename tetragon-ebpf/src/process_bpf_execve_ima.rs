@@ -0,0 +1,44 @@
+use crate::maps;
+use aya_ebpf::helpers::gen::{bpf_get_current_pid_tgid, bpf_ima_file_hash};
+use aya_ebpf::{macros::lsm, programs::LsmContext};
+use tetragon_common::process::{MsgImaHash, MAX_IMA_HASH_SIZE};
+use tetragon_common::vmlinux::linux_binprm;
+
+// bpf_ima_file_hash() can sleep, so it's only available in sleepable programs.
+// The hash is stored per task and picked up by sched_process_exec, which runs
+// after bprm_committed_creds in the same execve.
+#[lsm(hook = "bprm_committed_creds", sleepable)]
+pub fn ima_bprm_committed_creds(ctx: LsmContext) -> i32 {
+    let _ = unsafe { try_ima_bprm_committed_creds(ctx) };
+    0
+}
+
+unsafe fn try_ima_bprm_committed_creds(ctx: LsmContext) -> Result<u32, i64> {
+    let bprm: *const linux_binprm = ctx.arg(0);
+    // bpf_ima_file_hash() needs a BTF pointer, so don't use bpf_probe_read_kernel here
+    let file = (*bprm).file;
+
+    let mut hash = MsgImaHash::default();
+    let algo = bpf_ima_file_hash(
+        file as *mut _,
+        hash.value.as_mut_ptr() as *mut aya_ebpf_cty::c_void,
+        MAX_IMA_HASH_SIZE as u32,
+    );
+    if algo < 0 {
+        return Err(algo);
+    }
+    hash.algo = algo as i32;
+
+    let pid_tgid = bpf_get_current_pid_tgid();
+    maps::TG_IMA_HASH_MAP.insert(&pid_tgid, &hash, 0)?;
+    Ok(0)
+}
+
+#[inline]
+pub unsafe fn read_ima_hash(hash: &mut MsgImaHash) {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    if let Some(value) = maps::TG_IMA_HASH_MAP.get(&pid_tgid) {
+        *hash = *value;
+        let _ = maps::TG_IMA_HASH_MAP.remove(&pid_tgid);
+    }
+}
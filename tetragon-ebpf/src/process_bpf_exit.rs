@@ -1,6 +1,8 @@
 use crate::lib_process;
 use crate::maps;
-use aya_ebpf::helpers::{bpf_get_current_pid_tgid, bpf_get_current_task, bpf_probe_read_kernel};
+use aya_ebpf::helpers::{
+    bpf_get_current_pid_tgid, bpf_get_current_task, bpf_ktime_get_ns, bpf_probe_read_kernel,
+};
 use aya_ebpf::{macros::kprobe, programs::ProbeContext};
 use aya_log_ebpf::*;
 use core::mem;
@@ -18,6 +20,15 @@ pub fn exit_acct_process(ctx: ProbeContext) -> u32 {
 
 unsafe fn try_exit_acct_process(ctx: ProbeContext) -> Result<u32, i64> {
     let tgid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let task = bpf_get_current_task() as *const task_struct;
+
+    // Only the last thread of the group reports the exit of the process
+    let signal = bpf_probe_read_kernel(&(*task).signal)?;
+    let live = bpf_probe_read_kernel(&(*signal).live.counter)?;
+    if live != 0 {
+        return Ok(0);
+    }
+
     let _ = event_exit_send(&ctx, tgid);
     debug!(&ctx, "exit_acct_process: {}", tgid);
     Ok(0)
@@ -42,6 +53,7 @@ pub unsafe fn event_exit_send(ctx: &ProbeContext, tgid: __u32) -> Result<u32, i6
         exit.common.pad[0] = 0;
         exit.common.pad[1] = 0;
         exit.common.size = size as u32;
+        exit.common.ktime = bpf_ktime_get_ns();
 
         exit.current.pid = tgid;
         exit.current.pad[0] = 0;
@@ -50,7 +62,7 @@ pub unsafe fn event_exit_send(ctx: &ProbeContext, tgid: __u32) -> Result<u32, i6
         exit.current.pad[3] = 0;
         exit.current.ktime = enter.key.ktime;
 
-        exit.info.tid = tgid;
+        exit.info.tid = bpf_probe_read_kernel(&(*task).pid).unwrap_or(0) as u32;
         let exit_code = bpf_probe_read_kernel(&(*task).exit_code).unwrap_or(1);
         exit.info.code = exit_code.unsigned_abs();
 
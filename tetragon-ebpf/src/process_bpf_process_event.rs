@@ -1,12 +1,21 @@
 use crate::lib_bpf_cgroup::{__tg_get_current_cgroup_id, get_cgroup_name, get_task_cgroup};
+use crate::lib_helper::offset_of;
+use crate::maps;
+use crate::process_bpf_cgtracker::cgrp_get_tracker_id;
 use aya_ebpf::helpers::{
-    bpf_get_current_task, bpf_probe_read_kernel, bpf_probe_read_kernel_str_bytes,
+    bpf_get_current_task, bpf_probe_read_kernel, bpf_probe_read_kernel_str_bytes, gen,
 };
-use tetragon_common::bpf_cred::{MsgCapabilities, MsgCred};
-use tetragon_common::process::{MsgK8s, MsgNs, MsgProcess};
+use tetragon_common::bpf_cred::{MsgCapabilities, MsgCred, MsgUserNamespace};
+use tetragon_common::process::{MsgK8s, MsgNs, MsgProcess, CWD_MAX_LEN};
 use tetragon_common::vmlinux::*;
 
-use tetragon_common::flags::msg_flags::EVENT_CLONE;
+use tetragon_common::flags::msg_flags::{
+    EVENT_CLONE, EVENT_ERROR_CGROUP_ID, EVENT_ERROR_CGROUP_NAME, EVENT_ERROR_CWD,
+    EVENT_ERROR_PATH_COMPONENTS, EVENT_ROOT_CWD,
+};
+
+// Max number of path components walked to resolve the cwd
+const PROBE_CWD_READ_ITERATIONS: usize = 11;
 
 #[inline]
 pub unsafe fn get_current_subj_caps(task: *const task_struct) -> MsgCapabilities {
@@ -62,6 +71,9 @@ pub unsafe fn get_current_subj_creds(info: &mut MsgCred, task: *const task_struc
     info.fsuid = cred.fsuid.val;
     info.fsgid = cred.fsgid.val;
     info.securebits = cred.securebits;
+
+    __get_caps(&mut info.caps, &cred);
+    __get_user_ns(&mut info.user_ns, &cred);
 }
 
 #[inline]
@@ -71,6 +83,26 @@ pub unsafe fn __get_caps(msg: &mut MsgCapabilities, cred: &cred) {
     msg.permitted = cred.cap_permitted.val;
 }
 
+// The user namespace where the uids, gids and capabilities of cred are
+// relative to. user_namespace is too large for the stack, so read it field by
+// field.
+#[inline]
+pub unsafe fn __get_user_ns(msg: &mut MsgUserNamespace, cred: &cred) {
+    let ns = cred.user_ns;
+    if ns.is_null() {
+        return;
+    }
+
+    msg.level = bpf_probe_read_kernel(&(*ns).level).unwrap_or(0);
+    msg.uid = bpf_probe_read_kernel(&(*ns).owner)
+        .map(|owner| owner.val)
+        .unwrap_or(0);
+    msg.gid = bpf_probe_read_kernel(&(*ns).group)
+        .map(|group| group.val)
+        .unwrap_or(0);
+    msg.ns_inum = bpf_probe_read_kernel(&(*ns).ns.inum).unwrap_or(0);
+}
+
 #[inline]
 pub unsafe fn get_namespaces(msg: &mut MsgNs, task: *const task_struct) {
     let Ok(nsp) = bpf_probe_read_kernel(&(*task).nsproxy) else {
@@ -169,39 +201,39 @@ pub unsafe fn get_namespaces(msg: &mut MsgNs, task: *const task_struct) {
     }
 }
 
+/**
+ * __event_get_cgroup_info() Collects the cgroup id and name of the task
+ * @task: target task
+ * @kube: cgroup info of the event
+ *
+ * Returns the EVENT_ERROR_CGROUP* flags of the fields that couldn't be read.
+ */
 #[inline]
 pub unsafe fn __event_get_cgroup_info(task: *const task_struct, kube: &mut MsgK8s) -> __u32 {
-    // Now support only Cgroup v2
-
-    let cgrpfs_magic: __u64 = 0;
-    let subsys_idx: __u32 = 0;
-    // struct cgroup *cgrp;
-    // struct tetragon_conf *conf;
+    let mut cgrpfs_magic: __u64 = 0;
+    let mut subsys_idx: __u32 = 0;
     let mut flags: __u32 = 0;
 
-    // conf = map_lookup_elem(&tg_conf_map, &zero);
-    // if (conf) {
-    // 	/* Select which cgroup version */
-    // 	cgrpfs_magic = conf->cgrp_fs_magic;
-    // 	subsys_idx = conf->tg_cgrpv1_subsys_idx;
-    // }
+    // Select which cgroup version
+    if let Some(conf) = maps::TG_CONF_MAP.get(0) {
+        cgrpfs_magic = conf.cgrp_fs_magic;
+        subsys_idx = conf.tg_cgrpv1_subsys_idx;
+    }
 
     let Some(cgrp) = get_task_cgroup(task, cgrpfs_magic, subsys_idx, &mut flags) else {
-        return 1;
+        return flags;
     };
 
-    // /* Collect event cgroup ID */
+    // Collect event cgroup ID
     kube.cgrpid = __tg_get_current_cgroup_id(cgrp, cgrpfs_magic);
-    // if (kube->cgrpid)
-    // 	kube->cgrp_tracker_id = cgrp_get_tracker_id(kube->cgrpid);
-    // else
-    // 	flags |= EVENT_ERROR_CGROUP_ID;
-
-    // /* Get the cgroup name of this event. */
-    // flags |= __event_get_current_cgroup_name(cgrp, kube);
-    __event_get_current_cgroup_name(cgrp, kube);
-    // return flags;
-    return 0;
+    if kube.cgrpid != 0 {
+        kube.cgrp_tracker_id = cgrp_get_tracker_id(kube.cgrpid);
+    } else {
+        flags |= EVENT_ERROR_CGROUP_ID as __u32;
+    }
+
+    // Get the cgroup name of this event
+    flags | __event_get_current_cgroup_name(cgrp, kube)
 }
 
 #[inline]
@@ -213,9 +245,128 @@ pub unsafe fn event_set_clone(pid: &mut MsgProcess) {
 #[inline]
 pub unsafe fn __event_get_current_cgroup_name(cgrp: *const cgroup, kube: &mut MsgK8s) -> __u32 {
     let Some(name) = get_cgroup_name(cgrp) else {
-        return 1;
+        return EVENT_ERROR_CGROUP_NAME as __u32;
     };
 
-    let _ = bpf_probe_read_kernel_str_bytes(name as *const u8, &mut kube.docker_id);
-    return 0;
+    match bpf_probe_read_kernel_str_bytes(name, &mut kube.docker_id) {
+        Ok(_) => 0,
+        Err(_) => EVENT_ERROR_CGROUP_NAME as __u32,
+    }
+}
+
+#[inline]
+unsafe fn real_mount(vfsmnt: *const vfsmount) -> *const mount {
+    let off = offset_of::<mount>(|m| unsafe { &(*m).mnt as *const _ as *const u8 });
+    (vfsmnt as *const u8).sub(off) as *const mount
+}
+
+/**
+ * getcwd() Reads the current working directory of the task
+ * @task: target task
+ * @cwd: output buffer, left NUL terminated
+ *
+ * Walks the dentries of task->fs->pwd up to task->fs->root, crossing mount
+ * points, and prepends every component into a scratch buffer like d_path()
+ * does. Returns EVENT_ROOT_CWD when the cwd is the root directory,
+ * EVENT_ERROR_CWD when the task fs could not be read and
+ * EVENT_ERROR_PATH_COMPONENTS when the path was only partially resolved.
+ */
+#[inline]
+pub unsafe fn getcwd(task: *const task_struct, cwd: &mut [u8; CWD_MAX_LEN]) -> __u32 {
+    let Ok(fs) = bpf_probe_read_kernel(&(*task).fs) else {
+        return EVENT_ERROR_CWD as __u32;
+    };
+    let Ok(root) = bpf_probe_read_kernel(&(*fs).root) else {
+        return EVENT_ERROR_CWD as __u32;
+    };
+    let Ok(pwd) = bpf_probe_read_kernel(&(*fs).pwd) else {
+        return EVENT_ERROR_CWD as __u32;
+    };
+    let Some(heap) = maps::GARBAGE_HEAP.get_ptr_mut(0) else {
+        return EVENT_ERROR_CWD as __u32;
+    };
+    let buf = &mut (*heap).heap;
+
+    let mut dentry = pwd.dentry as *const dentry;
+    let mut vfsmnt = pwd.mnt as *const vfsmount;
+    let mut mnt = real_mount(vfsmnt);
+    // Keep the last byte of cwd for the NUL terminator
+    let mut off = CWD_MAX_LEN - 1;
+    let mut resolved = false;
+
+    for _ in 0..PROBE_CWD_READ_ITERATIONS {
+        if dentry == root.dentry as *const dentry && vfsmnt == root.mnt as *const vfsmount {
+            resolved = true;
+            break;
+        }
+
+        let Ok(mnt_root) = bpf_probe_read_kernel(&(*vfsmnt).mnt_root) else {
+            break;
+        };
+        let Ok(parent) = bpf_probe_read_kernel(&(*dentry).d_parent) else {
+            break;
+        };
+
+        if dentry == mnt_root as *const dentry || dentry == parent as *const dentry {
+            // Reached the root of this mount, continue on the parent mount
+            let Ok(mnt_parent) = bpf_probe_read_kernel(&(*mnt).mnt_parent) else {
+                break;
+            };
+            if mnt == mnt_parent as *const mount {
+                // Global root
+                resolved = true;
+                break;
+            }
+            let Ok(mountpoint) = bpf_probe_read_kernel(&(*mnt).mnt_mountpoint) else {
+                break;
+            };
+            dentry = mountpoint;
+            mnt = mnt_parent;
+            vfsmnt = &(*mnt).mnt;
+            continue;
+        }
+
+        let Ok(d_name) = bpf_probe_read_kernel(&(*dentry).d_name) else {
+            break;
+        };
+        let len = d_name.__bindgen_anon_1.__bindgen_anon_1.len as usize;
+        if len + 1 > off {
+            // Path is too long for CWD_MAX_LEN
+            break;
+        }
+        off -= len + 1;
+
+        // These masks are needed to pass verifier check
+        let off_m = off & (CWD_MAX_LEN - 1);
+        buf[off_m] = b'/';
+        gen::bpf_probe_read_kernel(
+            buf.as_mut_ptr().add(off_m + 1) as *mut aya_ebpf_cty::c_void,
+            (len & (CWD_MAX_LEN - 1)) as u32,
+            d_name.name as *const aya_ebpf_cty::c_void,
+        );
+
+        dentry = parent;
+    }
+
+    let size = CWD_MAX_LEN - 1 - off;
+    if size == 0 {
+        return if resolved {
+            EVENT_ROOT_CWD as __u32
+        } else {
+            EVENT_ERROR_PATH_COMPONENTS as __u32
+        };
+    }
+
+    let off_m = off & (CWD_MAX_LEN - 1);
+    gen::bpf_probe_read_kernel(
+        cwd.as_mut_ptr() as *mut aya_ebpf_cty::c_void,
+        (size & (CWD_MAX_LEN - 1)) as u32,
+        buf.as_ptr().add(off_m) as *const aya_ebpf_cty::c_void,
+    );
+
+    if resolved {
+        0
+    } else {
+        EVENT_ERROR_PATH_COMPONENTS as __u32
+    }
 }
@@ -1,9 +1,73 @@
+use crate::maps;
+use aya_ebpf::bindings::BPF_F_CURRENT_CPU;
+use aya_ebpf::helpers::gen::bpf_perf_event_output;
 use aya_ebpf::EbpfContext;
+use core::mem;
+use tetragon_common::msg_types::MsgOps;
 use tetragon_common::process::MsgK8s;
+use tetragon_common::rate::{CgroupRateKey, CgroupRateValue, MsgThrottle};
 use tetragon_common::vmlinux::*;
 
 #[inline]
-pub unsafe fn cgroup_rate<C: EbpfContext>(_ctx: &C, _msg_k8s: &mut MsgK8s, _time: __u64) -> bool {
-    // TODO
-    true
+unsafe fn send_throttle_event<C: EbpfContext>(ctx: &C, kube: &MsgK8s, time: __u64) {
+    let mut msg = MsgThrottle::default();
+    msg.common.op = MsgOps::MsgOpThrottle as u8;
+    msg.common.size = mem::size_of::<MsgThrottle>() as u32;
+    msg.common.ktime = time;
+    msg.kube = *kube;
+
+    bpf_perf_event_output(
+        ctx.as_ptr(),
+        &raw const maps::TCPMON_MAP as *mut aya_ebpf_cty::c_void,
+        BPF_F_CURRENT_CPU as u64,
+        &mut msg as *mut MsgThrottle as *mut aya_ebpf_cty::c_void,
+        mem::size_of::<MsgThrottle>() as u64,
+    );
+}
+
+/**
+ * cgroup_rate() Accounts an execve event to the cgroup of the task
+ * @ctx: program context
+ * @kube: cgroup info of the task
+ * @time: ktime of the event
+ *
+ * Returns false when the cgroup exceeds CgroupRateOptions.events within
+ * the last CgroupRateOptions.interval and the event must be dropped. The
+ * first event over the budget sends a MsgThrottle, and the cgroup stays
+ * throttled until userspace sees the rate go down and asks for the throttle
+ * to be cleared through CGROUP_RATE_CLEAR_MAP.
+ */
+#[inline]
+pub unsafe fn cgroup_rate<C: EbpfContext>(ctx: &C, kube: &mut MsgK8s, time: __u64) -> bool {
+    let Some(opt) = maps::CGROUP_RATE_OPTIONS_MAP.get(0) else {
+        return true;
+    };
+    let interval = opt.interval;
+    if interval == 0 || kube.cgrpid == 0 {
+        return true;
+    }
+
+    let key = CgroupRateKey { id: kube.cgrpid };
+    let Some(val) = maps::CGROUP_RATE_MAP.get_ptr_mut(&key) else {
+        let mut val = CgroupRateValue::new(time, interval);
+        val.curr = 1;
+        let _ = maps::CGROUP_RATE_MAP.insert(&key, &val, 0);
+        return true;
+    };
+    let val = &mut *val;
+
+    val.slide(time, interval);
+    val.curr += 1;
+
+    if val.throttled != 0 && maps::CGROUP_RATE_CLEAR_MAP.get(&key).copied() == Some(val.throttled) {
+        val.throttled = 0;
+        let _ = maps::CGROUP_RATE_CLEAR_MAP.remove(&key);
+    }
+
+    if val.throttled == 0 && val.rate(time, interval) > opt.events {
+        val.throttled = time;
+        send_throttle_event(ctx, kube, time);
+    }
+
+    val.throttled == 0
 }
@@ -3,9 +3,12 @@ fn main() {
         .build_server(true)
         .compile(
             &[
+                "proto/cri.proto",
+                "proto/nri.proto",
                 "proto/route_guide.proto",
                 "proto/sensors.proto",
                 "proto/tetragon.proto",
+                "proto/ttrpc.proto",
             ],
             &["proto"],
         )
@@ -37,8 +37,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         match &event {
             Event::ProcessExec(process_exec) => {
                 debug!("process_exec: {:?}", process_exec);
+                let container = process_exec
+                    .process
+                    .as_ref()
+                    .and_then(|p| p.pod.as_ref())
+                    .and_then(|pod| pod.container.as_ref());
+                let tag = match container {
+                    Some(c) if c.maybe_exec_probe => " [probe]",
+                    Some(c) if c.maybe_exec_session => " [exec]",
+                    _ => "",
+                };
                 println!(
-                    "🚀 process\t{}: {}: {} {} {}/{}",
+                    "🚀 process\t{}: {}: {} {} {}/{}{}",
                     process_exec.process.as_ref().unwrap().pid.unwrap(),
                     translate_uid(process_exec.process.as_ref().unwrap().uid.unwrap()),
                     process_exec.process.as_ref().unwrap().binary,
@@ -55,6 +65,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .and_then(|p| p.pod.as_ref())
                         .map(|pod| pod.name.as_str())
                         .unwrap_or(""),
+                    tag,
                 );
             }
             Event::ProcessExit(process_exit) => {
@@ -79,6 +90,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .unwrap_or(""),
                 );
             }
+            Event::ProcessThrottle(process_throttle) => {
+                debug!("process_throttle: {:?}", process_throttle);
+                println!(
+                    "🧬 throttle\t{}: {}",
+                    process_throttle.r#type().as_str_name(),
+                    process_throttle.cgroup,
+                );
+            }
             _ => {
                 unimplemented!()
             }
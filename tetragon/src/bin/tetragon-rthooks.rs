@@ -1,66 +1,118 @@
-// TODO: Support all options defined in Tetragon: https://github.com/cilium/tetragon/tree/41b2405f3689ea0179af30f29a048ca3a3c55566/contrib/tetragon-rthooks
+// OCI hook that sends the container lifecycle events to the agent, like
+// https://github.com/cilium/tetragon/tree/41b2405f3689ea0179af30f29a048ca3a3c55566/contrib/tetragon-rthooks
+//
+// The runtime runs it with the container state on stdin and the stage of the
+// hook, like createRuntime or poststop, as first argument.
+use anyhow::Context as _;
+use std::process::ExitCode;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
 use tetragon::api::fine_guidance_sensors_client::FineGuidanceSensorsClient;
-use tetragon::api::runtime_hook_request::Event;
-use tetragon::api::{CreateContainer, RuntimeHookRequest};
-use tetragon::api::{GetHealthStatusRequest, HealthStatusType};
-
+use tetragon::rthooks::oci::{self, HookType};
+use tonic::transport::Endpoint;
 use tonic::Request;
-
 use tracing::*;
 use tracing_subscriber::filter::{EnvFilter, LevelFilter};
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::builder()
-                .with_default_directive(LevelFilter::DEBUG.into())
-                .from_env_lossy(),
-        )
-        .init();
+static AGENT_ADDR: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("TETRAGON_RTHOOKS_AGENT_ADDR").unwrap_or("http://[::1]:10001".to_string())
+});
+
+// Timeout in seconds of the connection and of the request to the agent
+static TIMEOUT: LazyLock<Duration> = LazyLock::new(|| {
+    let secs = std::env::var("TETRAGON_RTHOOKS_TIMEOUT")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(10);
+    Duration::from_secs(secs)
+});
 
-    let mut sensor_client = FineGuidanceSensorsClient::connect("http://[::1]:10001").await?;
-    let response = sensor_client
-        .get_health(Request::new(GetHealthStatusRequest {
-            event_set: vec![HealthStatusType::Status.into()],
-        }))
-        .await?;
-    debug!("get_health | RESPONSE = {:?}", response);
+// By default the container runs even when the agent can't be reached. When
+// fail closed, the hook fails and so does the container.
+static FAIL_CLOSED: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("TETRAGON_RTHOOKS_FAIL_CLOSED")
+        .map(|s| s == "1")
+        .unwrap_or(false)
+});
 
-    let annotations_data = [
-        ("org.opencontainers.image.os", "os value"),
-        ("org.opencontainers.image.os.version", "os.version value"),
-        ("org.opencontainers.image.os.features", "os.features value"),
-        (
-            "org.opencontainers.image.architecture",
-            "architecture value",
-        ),
-        ("org.opencontainers.image.variant", "variant value"),
-        ("org.opencontainers.image.author", "author value"),
-        ("org.opencontainers.image.created", "created value"),
-        ("org.opencontainers.image.stopSignal", "stopSignal value"),
-    ];
-    let annotations = annotations_data
-        .iter()
-        .map(|(k, v)| (k.to_string(), v.to_string()))
-        .collect();
+// The output of the hooks is not shown by the runtimes, so logs go to a file.
+// When empty, they go to stderr.
+static LOG_FILE: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("TETRAGON_RTHOOKS_LOG_FILE")
+        .unwrap_or("/var/log/tetragon-rthooks.log".to_string())
+});
 
-    let response = sensor_client
-        .runtime_hook(Request::new(RuntimeHookRequest {
-            event: Some(Event::CreateContainer(CreateContainer {
-                cgroups_path: "kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod3b673e1d_289e_4210_8ceb_5a253b48d390.slice/cri-containerd-5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147.scope".to_string(),
-                root_dir: "root_dir".to_string(),
-                container_name: "nginx_container".to_string(),
-                container_id: "".to_string(),
-                pod_name: "nginx-pod".to_string(),
-                pod_uid: "3b673e1d-289e-4210-8ceb-5a253b48d390".to_string(),
-                pod_namespace: "default".to_string(),
-                annotations,
-            })),
-        }))
-        .await?;
+fn init_logging() {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    if LOG_FILE.is_empty() {
+        subscriber.with_writer(std::io::stderr).init();
+        return;
+    }
+
+    match std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG_FILE.as_str())
+    {
+        Ok(file) => subscriber
+            .with_writer(Mutex::new(file))
+            .with_ansi(false)
+            .init(),
+        Err(e) => {
+            subscriber.with_writer(std::io::stderr).init();
+            warn!("failed to open {}: {}, logging to stderr", *LOG_FILE, e);
+        }
+    }
+}
 
-    info!("runtime_hook | RESPONSE = {:?}", response);
+async fn run(hook: HookType) -> anyhow::Result<()> {
+    let state = oci::read_state(std::io::stdin().lock())?;
+    let spec = oci::read_spec(&state.bundle)?;
+    let request = oci::hook_request(hook, &state, &spec);
+    debug!("{:?} request: {:?}", hook, request);
 
+    let channel = Endpoint::from_shared(AGENT_ADDR.clone())?
+        .connect_timeout(*TIMEOUT)
+        .timeout(*TIMEOUT)
+        .connect()
+        .await
+        .with_context(|| format!("failed to connect to {}", *AGENT_ADDR))?;
+    FineGuidanceSensorsClient::new(channel)
+        .runtime_hook(Request::new(request))
+        .await
+        .context("runtime hook request failed")?;
+
+    info!("{:?} hook of container {} done", hook, state.id);
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    init_logging();
+
+    let stage = std::env::args()
+        .nth(1)
+        .unwrap_or("createRuntime".to_string());
+    // An unknown stage, like a typo in the hook config, fails like the agent
+    // being unreachable: only when fail closed
+    let result = match HookType::parse(&stage) {
+        Some(hook) => run(hook).await,
+        None => Err(anyhow::anyhow!("unsupported hook")),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if *FAIL_CLOSED => {
+            error!("{} hook failed: {:#}", stage, e);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            warn!("{} hook failed, ignored: {:#}", stage, e);
+            ExitCode::SUCCESS
+        }
+    }
+}
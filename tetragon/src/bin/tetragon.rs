@@ -2,13 +2,21 @@ use futures::future::{FutureExt, TryFutureExt};
 use tetragon::api::get_events_response::Event;
 use tetragon::bpf::{
     init_ebpf,
-    maps::{get_process_events_map, write_execve_map},
+    maps::{get_process_events_map, write_execve_map, write_tg_conf},
 };
 use tetragon::cgidmap;
+use tetragon::cgrouprate;
+use tetragon::cgroups;
+use tetragon::cgtracker;
 use tetragon::metrics::*;
+use tetragon::nri;
 use tetragon::observer::run_events;
 use tetragon::podhelpers::extract_container_ids_from_event;
-use tetragon::process::{print_struct_size, procfs::initial_execve_map_valuses};
+use tetragon::process::{
+    envs::envs_enabled,
+    print_struct_size,
+    procfs::{add_initial_processes, initial_execve_map_valuses},
+};
 use tetragon::rthooks;
 use tetragon::server::FineGuidanceSensorsService;
 use tetragon::util::{shutdown_signals, stop_signal};
@@ -20,18 +28,48 @@ async fn app_main() -> anyhow::Result<()> {
     let meter_provider = init_metrics();
     let trace_provider = init_traces();
 
-    rthooks::init_runner();
-
     let (stop_tx, _stop_rx) = tokio::sync::broadcast::channel::<()>(1);
     let (event_tx, event_rx) = tokio::sync::broadcast::channel::<Event>(1);
 
     let (store, informer) = watcher::pod_informer();
+    rthooks::init_runner(store.clone());
 
     let (mut bpf, _execve_calls_map_guard) = init_ebpf()?;
+    cgroups::linux::detect_cgroup_mode();
+    cgroups::linux::detect_deployment_mode()?;
+    // Decided from the deployment mode, so only after its detection
+    if !*watcher::ENABLE_K8S_API {
+        info!("Running without Kubernetes, events have local containers and systemd units");
+    }
+    let mut conf = cgroups::linux::tg_conf();
+    conf.env_vars_enabled = envs_enabled() as u32;
+    write_tg_conf(&mut bpf, conf)?;
+
+    cgtracker::init(&mut bpf)?;
+    if let Err(e) = cgidmap::bootstrap::bootstrap() {
+        warn!("Failed to bootstrap cgidmap: {:?}", e);
+    }
 
     let execve_map_values = initial_execve_map_valuses()?;
     write_execve_map(&mut bpf, execve_map_values).await?;
 
+    let cgroup_rate_map = cgrouprate::init(&mut bpf)?;
+    let cgrouprate_thread = tokio::spawn({
+        let stop = stop_signal(stop_tx.subscribe());
+        let event_tx = event_tx.clone();
+        async move { cgrouprate::run(cgroup_rate_map, event_tx, stop).await }
+    });
+
+    let cri_resolver_thread = tokio::spawn({
+        let stop = stop_signal(stop_tx.subscribe());
+        async move { cgidmap::cri::run(stop).await }
+    });
+
+    let nri_thread = tokio::spawn({
+        let stop = stop_signal(stop_tx.subscribe());
+        async move { nri::run(stop).await }
+    });
+
     let store_clone = store.clone();
     let ebpf_thread = tokio::spawn({
         let stop = stop_signal(stop_tx.subscribe());
@@ -93,6 +131,20 @@ async fn app_main() -> anyhow::Result<()> {
         }
     });
 
+    let procfs_thread = tokio::spawn({
+        let stop = stop_signal(stop_tx.subscribe());
+        let store = store.clone();
+        async move {
+            tokio::pin!(stop);
+            tokio::select! {
+                result = add_initial_processes(store) => result?,
+                _ = &mut stop => return Ok(()),
+            }
+            stop.await;
+            Ok::<_, anyhow::Error>(())
+        }
+    });
+
     let informer_thread = tokio::spawn({
         let stop = stop_signal(stop_tx.subscribe());
         async move {
@@ -138,6 +190,26 @@ async fn app_main() -> anyhow::Result<()> {
                 .map(flatten)
                 .map(|r| ("informer_thread", r))
                 .boxed(),
+            cgrouprate_thread
+                .map_err(anyhow::Error::new)
+                .map(flatten)
+                .map(|r| ("cgrouprate_thread", r))
+                .boxed(),
+            cri_resolver_thread
+                .map_err(anyhow::Error::new)
+                .map(flatten)
+                .map(|r| ("cri_resolver_thread", r))
+                .boxed(),
+            nri_thread
+                .map_err(anyhow::Error::new)
+                .map(flatten)
+                .map(|r| ("nri_thread", r))
+                .boxed(),
+            procfs_thread
+                .map_err(anyhow::Error::new)
+                .map(flatten)
+                .map(|r| ("procfs_thread", r))
+                .boxed(),
         ])
     };
 
@@ -1,7 +1,11 @@
 use anyhow::Ok;
 use aya::maps::perf::AsyncPerfEventArray;
-use aya::{maps::HashMap, Ebpf};
+use aya::{
+    maps::{Array, HashMap},
+    Ebpf,
+};
 use std::convert::TryFrom;
+use tetragon_common::conf::TetragonConf;
 use tetragon_common::process::ExecveMapValue;
 use tetragon_common::vmlinux::*;
 use tracing::*;
@@ -9,6 +13,7 @@ use tracing::*;
 const EXECVE_MAP: &str = "EXECVE_MAP";
 pub(crate) const PROCESS_EVENTS_MAP: &str = "TCPMON_MAP";
 pub(crate) const EXECVE_CALLS: &str = "EXECVE_CALLS";
+const TG_CONF_MAP: &str = "TG_CONF_MAP";
 
 pub async fn write_execve_map(bpf: &mut Ebpf, values: Vec<ExecveMapValue>) -> anyhow::Result<()> {
     let mut execve_map: HashMap<_, __u32, ExecveMapValue> =
@@ -23,6 +28,14 @@ pub async fn write_execve_map(bpf: &mut Ebpf, values: Vec<ExecveMapValue>) -> an
     Ok(())
 }
 
+pub fn write_tg_conf(bpf: &mut Ebpf, conf: TetragonConf) -> anyhow::Result<()> {
+    let mut conf_map: Array<_, TetragonConf> = Array::try_from(bpf.map_mut(TG_CONF_MAP).unwrap())?;
+    conf_map.set(0, conf, 0)?;
+
+    info!("Wrote tetragon conf into map: {:?}", conf);
+    Ok(())
+}
+
 pub fn get_process_events_map(
     bpf: &mut Ebpf,
 ) -> anyhow::Result<AsyncPerfEventArray<aya::maps::MapData>> {
@@ -1,9 +1,10 @@
 pub mod maps;
 
+use crate::process::hash::EXPORT_BINARY_HASH;
 use aya::{
     include_bytes_aligned,
     maps::{MapData, ProgramArray},
-    programs::{BtfTracePoint, KProbe},
+    programs::{BtfTracePoint, KProbe, Lsm},
     Btf, Ebpf,
 };
 use aya_log::EbpfLogger;
@@ -51,6 +52,27 @@ fn attach_programs(bpf: &mut Ebpf, btf: &Btf) -> anyhow::Result<ProgramArray<aya
     program.load()?;
     program.attach("wake_up_new_task", 0)?;
 
+    let program: &mut KProbe = bpf
+        .program_mut("tg_kp_bprm_committing_creds")
+        .unwrap()
+        .try_into()?;
+    program.load()?;
+    program.attach("security_bprm_committing_creds", 0)?;
+
+    let program: &mut BtfTracePoint = bpf
+        .program_mut("tg_cgtracker_cgroup_mkdir")
+        .unwrap()
+        .try_into()?;
+    program.load("cgroup_mkdir", btf)?;
+    program.attach()?;
+
+    let program: &mut BtfTracePoint = bpf
+        .program_mut("tg_cgtracker_cgroup_rmdir")
+        .unwrap()
+        .try_into()?;
+    program.load("cgroup_rmdir", btf)?;
+    program.attach()?;
+
     let flags = 0;
 
     let mut execve_calls_map: ProgramArray<aya::maps::MapData> =
@@ -80,5 +102,25 @@ fn attach_programs(bpf: &mut Ebpf, btf: &Btf) -> anyhow::Result<ProgramArray<aya
     program.load("sched_process_exec", btf)?;
     program.attach()?;
 
+    if *EXPORT_BINARY_HASH {
+        // Requires the BPF LSM and IMA, binaries are hashed in userspace otherwise
+        if let Err(e) = attach_ima_program(bpf, btf) {
+            warn!(
+                "IMA hash is not available, falling back to userspace: {}",
+                e
+            );
+        }
+    }
+
     Ok(execve_calls_map)
 }
+
+fn attach_ima_program(bpf: &mut Ebpf, btf: &Btf) -> anyhow::Result<()> {
+    let program: &mut Lsm = bpf
+        .program_mut("ima_bprm_committed_creds")
+        .unwrap()
+        .try_into()?;
+    program.load("bprm_committed_creds", btf)?;
+    program.attach()?;
+    Ok(())
+}
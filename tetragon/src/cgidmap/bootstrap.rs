@@ -0,0 +1,198 @@
+use crate::cgidmap::{self, CgroupID, ContainerID, PodID};
+use crate::cgroups::linux::{get_cgroup_id_from_sub_cgroup, host_cgroup_root, CGROUP_CONTROLLERS};
+use crate::cgtracker;
+use crate::rthooks::args::{container_id_from_cgroup_path, pod_id_from_cgroup_path};
+use std::path::{Path, PathBuf};
+use tracing::*;
+use uuid::Uuid;
+
+const KUBEPODS_PREFIX: &str = "kubepods";
+
+#[derive(Debug, PartialEq)]
+struct ScannedContainer {
+    pod_id: PodID,
+    cont_id: ContainerID,
+    cg_id: CgroupID,
+    path: PathBuf,
+}
+
+// Returns the kubepods cgroups below cg_root. With cgroup v1 they live in the
+// hierarchy of a controller, like memory/kubepods.
+fn kubepods_roots(cg_root: &Path) -> Vec<PathBuf> {
+    let find = |dir: &Path| -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(KUBEPODS_PREFIX)
+            })
+            .map(|entry| entry.path())
+            .collect()
+    };
+
+    let roots = find(cg_root);
+    if !roots.is_empty() {
+        return roots;
+    }
+    CGROUP_CONTROLLERS
+        .iter()
+        .map(|c| find(&cg_root.join(&c.name)))
+        .find(|roots| !roots.is_empty())
+        .unwrap_or_default()
+}
+
+// Returns the container in the cgroup at path, which is below a pod cgroup
+// like .../pod<uid>/<id> or .../kubepods-<qos>-pod<uid>.slice/cri-containerd-<id>.scope
+// For static pods, the uid is the one of the static pod, moved to the mirror
+// pod when the informer sees it.
+fn container_from_path(path: &Path) -> Option<(PodID, ContainerID)> {
+    let path_str = path.to_string_lossy();
+    let name = path.file_name()?.to_string_lossy();
+    // The conmon of CRI-O runs in its own cgroup, next to the container
+    if name.starts_with("crio-conmon-") {
+        return None;
+    }
+
+    let cont_id = container_id_from_cgroup_path(&path_str);
+    if cont_id.len() != 64 || !cont_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    // The systemd driver escapes the dashes of the uid
+    let pod_id = pod_id_from_cgroup_path(&path_str).replace('_', "-");
+    let pod_id = Uuid::parse_str(&pod_id).ok()?;
+
+    Some((pod_id, cont_id))
+}
+
+fn scan_dir(dir: &Path, containers: &mut Vec<ScannedContainer>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let path = entry.path();
+
+        let Some((pod_id, cont_id)) = container_from_path(&path) else {
+            scan_dir(&path, containers);
+            continue;
+        };
+        match get_cgroup_id_from_sub_cgroup(&path.to_string_lossy()) {
+            Ok(cg_id) => containers.push(ScannedContainer {
+                pod_id,
+                cont_id,
+                cg_id,
+                path,
+            }),
+            Err(e) => warn!(
+                "cgidmap: failed to get the cgroup id of {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+fn scan(cg_root: &Path) -> Vec<ScannedContainer> {
+    let mut containers = Vec::new();
+    for root in kubepods_roots(cg_root) {
+        scan_dir(&root, &mut containers);
+    }
+    containers
+}
+
+// Fills the cgidmap with the containers already running on the host, so that
+// their processes are attributed to pods after an agent restart. Runs before
+// the pod informer starts, so that its first sync finds them mapped already.
+pub fn bootstrap() -> anyhow::Result<()> {
+    let cg_root = host_cgroup_root()?;
+    let containers = scan(Path::new(&cg_root));
+
+    for c in &containers {
+        cgidmap::add(c.pod_id, c.cont_id.clone(), c.cg_id);
+        if let Err(e) = cgtracker::add_cgroup_tracker_path(&c.path.to_string_lossy()) {
+            warn!("cgidmap: failed to add path to cgroup tracker: {}", e);
+        }
+    }
+
+    info!(
+        "cgidmap: bootstrapped {} containers from {}",
+        containers.len(),
+        cg_root
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgroups::linux::get_cgroup_id_from_path;
+
+    const POD_UID: &str = "4c9f1974-5c46-44c2-b42f-3bbf0e98eef9";
+    const CONT_ID: &str = "bacb920470900725e0aa7d914fee5eb0854315448b024b6b8420ad8429c607ba";
+
+    #[test]
+    fn test_container_from_path() {
+        let pod_id = Uuid::parse_str(POD_UID).unwrap();
+        let test_cases = vec![
+            format!("/kubepods/besteffort/pod{}/{}", POD_UID, CONT_ID),
+            format!("/kubepods/burstable/pod{}/crio-{}", POD_UID, CONT_ID),
+            format!(
+                "/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod{}.slice/cri-containerd-{}.scope",
+                POD_UID.replace('-', "_"),
+                CONT_ID
+            ),
+        ];
+        for path in test_cases {
+            assert_eq!(
+                container_from_path(Path::new(&path)),
+                Some((pod_id, CONT_ID.to_string())),
+                "{}",
+                path
+            );
+        }
+
+        let not_containers = vec![
+            "/kubepods/besteffort".to_string(),
+            format!("/kubepods/besteffort/pod{}", POD_UID),
+            format!(
+                "/kubepods/besteffort/pod{}/crio-conmon-{}",
+                POD_UID, CONT_ID
+            ),
+        ];
+        for path in not_containers {
+            assert_eq!(container_from_path(Path::new(&path)), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = std::env::temp_dir().join(format!("cgidmap-bootstrap-{}", std::process::id()));
+        let pod = root.join(format!("kubepods/besteffort/pod{}", POD_UID));
+        std::fs::create_dir_all(pod.join(CONT_ID)).unwrap();
+        std::fs::create_dir_all(pod.join(format!("crio-conmon-{}", CONT_ID))).unwrap();
+        std::fs::create_dir_all(root.join("system.slice/foo.service")).unwrap();
+        let cg_id = get_cgroup_id_from_path(&pod.join(CONT_ID).to_string_lossy()).unwrap();
+
+        let containers = scan(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(
+            containers,
+            vec![ScannedContainer {
+                pod_id: Uuid::parse_str(POD_UID).unwrap(),
+                cont_id: CONT_ID.to_string(),
+                cg_id,
+                path: pod.join(CONT_ID),
+            }]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use crate::cgidmap::{self, CgroupID, ContainerID, PodID};
+use crate::cgroups::linux::{get_cgroup_id_from_sub_cgroup, host_cgroup_root};
+use crate::cri::{self, CriClient};
+use crate::rthooks::args::host_cgroup_path;
+use lru::LruCache;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::*;
+
+// Unmapped ids are dropped after this many failed attempts
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// Ids the CRI runtime doesn't know are remembered, so that they are not
+// queried again on every update of their pod
+const NOT_FOUND_CACHE_SIZE: usize = 4096;
+
+// A running container of a pod that is not in the cgidmap, e.g. because it
+// was created before the agent started and the runtime hook never fired.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct UnmappedID {
+    pub pod_id: PodID,
+    pub cont_id: ContainerID,
+    pub attempts: u32,
+}
+
+struct Queue {
+    pending: Vec<UnmappedID>,
+    // Ids pending or being resolved, so that each is queued once
+    queued: HashSet<ContainerID>,
+    not_found: LruCache<ContainerID, ()>,
+}
+
+static QUEUE: LazyLock<Mutex<Queue>> = LazyLock::new(|| {
+    Mutex::new(Queue {
+        pending: Vec::new(),
+        queued: HashSet::new(),
+        not_found: LruCache::new(NonZeroUsize::new(NOT_FOUND_CACHE_SIZE).unwrap()),
+    })
+});
+static NOTIFY: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+// Schedules ids to be resolved by the CRI resolver, unless they are already
+// queued or unknown to the runtime
+pub(crate) fn enqueue(ids: Vec<UnmappedID>) {
+    let mut queue = QUEUE.lock().unwrap();
+    let mut added = false;
+    for id in ids {
+        if queue.not_found.contains(&id.cont_id) || !queue.queued.insert(id.cont_id.clone()) {
+            continue;
+        }
+        queue.pending.push(id);
+        added = true;
+    }
+    if added {
+        NOTIFY.notify_one();
+    }
+}
+
+async fn resolve_one(
+    client: &mut CriClient,
+    cg_root: &str,
+    cont_id: &str,
+) -> anyhow::Result<CgroupID> {
+    let cgroups_path = cri::cgroup_path(client, cont_id).await?;
+    let path = host_cgroup_path(cg_root, &cgroups_path);
+    Ok(get_cgroup_id_from_sub_cgroup(&path)?)
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<tonic::Status>()
+        .is_some_and(|status| status.code() == tonic::Code::NotFound)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Resolved {
+    mapped: Vec<(PodID, ContainerID, CgroupID)>,
+    retry: Vec<UnmappedID>,
+    // Unknown to the runtime, like the containers of other nodes
+    not_found: Vec<ContainerID>,
+}
+
+async fn resolve(client: &mut CriClient, cg_root: &str, pending: Vec<UnmappedID>) -> Resolved {
+    let mut resolved = Resolved::default();
+
+    for mut id in pending {
+        match resolve_one(client, cg_root, &id.cont_id).await {
+            Ok(cg_id) => resolved.mapped.push((id.pod_id, id.cont_id, cg_id)),
+            Err(e) if is_not_found(&e) => {
+                debug!("cri resolver: {} not found, dropped", id.cont_id);
+                resolved.not_found.push(id.cont_id);
+            }
+            Err(e) => {
+                id.attempts += 1;
+                if id.attempts < MAX_ATTEMPTS {
+                    debug!(
+                        "cri resolver: failed to resolve {}, will retry: {}",
+                        id.cont_id, e
+                    );
+                    resolved.retry.push(id);
+                } else {
+                    warn!("cri resolver: giving up on {}: {}", id.cont_id, e);
+                }
+            }
+        }
+    }
+
+    resolved
+}
+
+// Resolves the cgroup ids of unmapped containers with the CRI runtime service
+// and adds them to the cgidmap. Disabled when no CRI socket is found.
+pub async fn run(stop: impl std::future::Future<Output = ()>) -> anyhow::Result<()> {
+    let Some(socket) = cri::endpoint() else {
+        info!("cri resolver: no CRI endpoint found, disabled");
+        stop.await;
+        return Ok(());
+    };
+    let cg_root = host_cgroup_root()?;
+    info!("cri resolver: using {}", socket);
+
+    let mut client: Option<CriClient> = None;
+    futures::pin_mut!(stop);
+    loop {
+        let has_pending = !QUEUE.lock().unwrap().pending.is_empty();
+        tokio::select! {
+            _ = NOTIFY.notified() => {}
+            _ = tokio::time::sleep(RETRY_INTERVAL), if has_pending => {}
+            _ = &mut stop => break,
+        }
+
+        let pending = std::mem::take(&mut QUEUE.lock().unwrap().pending);
+        if pending.is_empty() {
+            continue;
+        }
+
+        if client.is_none() {
+            client = cri::connect(&socket)
+                .await
+                .inspect_err(|e| warn!("cri resolver: failed to connect to {}: {}", socket, e))
+                .ok();
+        }
+        let Some(client) = client.as_mut() else {
+            QUEUE.lock().unwrap().pending.extend(pending);
+            continue;
+        };
+
+        let ids: Vec<ContainerID> = pending.iter().map(|id| id.cont_id.clone()).collect();
+        let resolved = resolve(client, &cg_root, pending).await;
+        for (pod_id, cont_id, cg_id) in resolved.mapped {
+            cgidmap::add(pod_id, cont_id, cg_id);
+        }
+
+        let mut queue = QUEUE.lock().unwrap();
+        for id in ids {
+            if !resolved.retry.iter().any(|retry| retry.cont_id == id) {
+                queue.queued.remove(&id);
+            }
+        }
+        for id in resolved.not_found {
+            queue.not_found.put(id, ());
+        }
+        queue.pending.extend(resolved.retry);
+    }
+
+    info!("cri resolver terminated");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgroups::linux::get_cgroup_id_from_path;
+    use crate::cri_api::runtime_service_server::{RuntimeService, RuntimeServiceServer};
+    use crate::cri_api::{ContainerStatus, ContainerStatusRequest, ContainerStatusResponse};
+    use std::collections::HashMap;
+    use tokio::net::UnixListener;
+    use tokio_stream::wrappers::UnixListenerStream;
+    use tonic::{Request, Response, Status};
+    use uuid::Uuid;
+
+    // Fake CRI runtime service, keyed by container id to cgroupsPath
+    struct FakeRuntime {
+        containers: HashMap<String, String>,
+    }
+
+    #[tonic::async_trait]
+    impl RuntimeService for FakeRuntime {
+        async fn container_status(
+            &self,
+            request: Request<ContainerStatusRequest>,
+        ) -> Result<Response<ContainerStatusResponse>, Status> {
+            let id = request.into_inner().container_id;
+            let Some(cgroups_path) = self.containers.get(&id) else {
+                return Err(Status::not_found(format!("container {} not found", id)));
+            };
+
+            let info = serde_json::json!({
+                "runtimeSpec": { "linux": { "cgroupsPath": cgroups_path } }
+            });
+            Ok(Response::new(ContainerStatusResponse {
+                status: Some(ContainerStatus { id }),
+                info: HashMap::from([("info".to_string(), info.to_string())]),
+            }))
+        }
+    }
+
+    fn fake_cri_server(socket: &str, containers: HashMap<String, String>) {
+        let listener = UnixListener::bind(socket).unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(RuntimeServiceServer::new(FakeRuntime { containers }))
+                .serve_with_incoming(UnixListenerStream::new(listener)),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let root = std::env::temp_dir().join(format!("cri-resolver-{}", std::process::id()));
+        let cont_path = "kubepods/besteffort/pod1234/cont1";
+        std::fs::create_dir_all(root.join(cont_path)).unwrap();
+        let cg_root = root.to_string_lossy().into_owned();
+        let cg_id = get_cgroup_id_from_path(&root.join(cont_path).to_string_lossy()).unwrap();
+
+        // The cgroups of cont2 and cont3 are not created yet, cont4 is unknown
+        let socket = root.join("cri.sock").to_string_lossy().into_owned();
+        fake_cri_server(
+            &socket,
+            HashMap::from([
+                ("cont1".to_string(), format!("/{}", cont_path)),
+                (
+                    "cont2".to_string(),
+                    "/kubepods/besteffort/pod1234/cont2".to_string(),
+                ),
+                (
+                    "cont3".to_string(),
+                    "/kubepods/besteffort/pod1234/cont3".to_string(),
+                ),
+            ]),
+        );
+        let mut client = cri::connect(&socket).await.unwrap();
+
+        let pod_id = Uuid::new_v4();
+        let unmapped = |cont_id: &str, attempts| UnmappedID {
+            pod_id,
+            cont_id: cont_id.to_string(),
+            attempts,
+        };
+        let pending = vec![
+            unmapped("cont1", 0),
+            unmapped("cont2", 0),
+            unmapped("cont3", MAX_ATTEMPTS - 1),
+            unmapped("cont4", 0),
+        ];
+
+        let resolved = resolve(&mut client, &cg_root, pending).await;
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(
+            resolved,
+            Resolved {
+                mapped: vec![(pod_id, "cont1".to_string(), cg_id)],
+                // cont3 ran out of attempts
+                retry: vec![unmapped("cont2", 1)],
+                not_found: vec!["cont4".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_enqueue() {
+        let pod_id = Uuid::new_v4();
+        let unmapped = |cont_id: &str| UnmappedID {
+            pod_id,
+            cont_id: cont_id.to_string(),
+            attempts: 0,
+        };
+        QUEUE
+            .lock()
+            .unwrap()
+            .not_found
+            .put("enqueue-gone".to_string(), ());
+
+        // Queued once, and never when unknown to the runtime
+        enqueue(vec![unmapped("enqueue-1"), unmapped("enqueue-gone")]);
+        enqueue(vec![unmapped("enqueue-1"), unmapped("enqueue-2")]);
+
+        let queue = QUEUE.lock().unwrap();
+        let queued: Vec<&str> = queue
+            .pending
+            .iter()
+            .map(|id| id.cont_id.as_str())
+            .filter(|id| id.starts_with("enqueue-"))
+            .collect();
+        assert_eq!(queued, vec!["enqueue-1", "enqueue-2"]);
+    }
+}
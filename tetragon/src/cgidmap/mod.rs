@@ -1,3 +1,5 @@
+pub mod bootstrap;
+pub mod cri;
 pub mod podhooks;
 pub mod rthooks;
 use std::collections::{HashMap, HashSet};
@@ -27,7 +29,6 @@ pub(crate) struct CgidMap {
     // TODO
     // log logrus.FieldLogger
     // *logger.DebugLogger
-    // criResolver *criResolver
 }
 
 impl CgidMap {
@@ -81,6 +82,17 @@ impl CgidMap {
             old_entry.cg_id = new_entry.cg_id;
         }
     }
+
+    fn rename_pod(&mut self, old_id: PodID, new_id: PodID) -> usize {
+        let mut renamed = 0;
+        for e in self.entries.iter_mut() {
+            if !e.invalid && e.pod_id == old_id {
+                e.pod_id = new_id;
+                renamed += 1;
+            }
+        }
+        renamed
+    }
 }
 
 pub fn add(pod_id: PodID, cont_id: ContainerID, cg_id: CgroupID) {
@@ -108,6 +120,19 @@ pub fn get(cg_id: CgroupID) -> Option<ContainerID> {
     None
 }
 
+// Moves the entries of a pod to another pod id. The containers of a static pod
+// are found in the cgroups by the uid of the static pod, while the API server
+// knows them by the one of its mirror pod.
+pub fn rename_pod(old_id: PodID, new_id: PodID) {
+    let renamed = CGID_MAP.lock().unwrap().rename_pod(old_id, new_id);
+    if renamed > 0 {
+        info!(
+            "cgidmap: moved {} containers of pod {} to pod {}",
+            renamed, old_id, new_id
+        );
+    }
+}
+
 // Update updates the cgid map for the container ids of a given pod
 pub fn update(pod_id: PodID, cont_ids: &mut HashSet<ContainerID>) {
     let mut remove_cg = Vec::new();
@@ -153,21 +178,16 @@ pub fn update(pod_id: PodID, cont_ids: &mut HashSet<ContainerID>) {
     );
     info!("cgidmap: current entries: {:?}", map.entries.iter());
 
-    // if cont_ids.is_empty() {
-    //     return;
-    // }
-
-    // TODO: schedule unmapped ids to be resolved by the CRI resolver
-    // unmappedIDs := make([]unmappedID, 0, len(tmp))
-    // for id := range tmp {
-    // 	unmappedIDs = append(unmappedIDs, unmappedID{
-    // 		podID:  podID,
-    // 		contID: id,
-    // 	})
-    // }
-    // if m.criResolver != nil {
-    // 	m.criResolver.enqeue(unmappedIDs)
-    // }
+    // schedule unmapped ids to be resolved by the CRI resolver
+    let unmapped = cont_ids
+        .drain()
+        .map(|cont_id| cri::UnmappedID {
+            pod_id,
+            cont_id,
+            attempts: 0,
+        })
+        .collect();
+    cri::enqueue(unmapped);
 }
 
 #[cfg(test)]
@@ -281,4 +301,21 @@ mod tests {
         }
         teardown();
     }
+
+    #[test]
+    fn test_rename_pod() {
+        let static_id = Uuid::parse_str("6d8e1e3b6a4e4bd2f37ab3c5c0b9f1a2").unwrap();
+        let mirror_id = Uuid::parse_str("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d9").unwrap();
+        let mut map = CgidMap::new();
+        map.add_entry(Entry {
+            cg_id: 123450009,
+            cont_id: "container009".to_string(),
+            pod_id: static_id,
+            invalid: false,
+        });
+
+        assert_eq!(map.rename_pod(static_id, mirror_id), 1);
+        assert_eq!(map.entries[0].pod_id, mirror_id);
+        assert_eq!(map.rename_pod(static_id, mirror_id), 0);
+    }
 }
@@ -1,9 +1,12 @@
 use crate::cgidmap;
 use crate::podhelpers::{extract_container_ids, parse_uuid};
+use crate::watcher::index::config_hash;
+use crate::watcher::NODE_NAME;
 use k8s_openapi::api::core::v1::Pod;
 use kube::runtime::watcher;
 use tokio::sync::broadcast;
 use tracing::*;
+use uuid::Uuid;
 
 pub async fn run(
     mut receiver: broadcast::Receiver<watcher::Event<Pod>>,
@@ -30,11 +33,33 @@ pub async fn run(
     Ok(())
 }
 
+// Whether the pod runs on another node than the one of the agent, whose
+// containers can't be in the cgidmap
+fn on_other_node(pod: &Pod, node: Option<&str>) -> bool {
+    let pod_node = pod.spec.as_ref().and_then(|spec| spec.node_name.as_deref());
+    matches!((node, pod_node), (Some(node), Some(pod_node)) if node != pod_node)
+}
+
+// Moves the containers of a static pod, mapped by the uid of the static pod at
+// bootstrap, to its mirror pod. The uid of a static pod is the hash its mirror
+// pod is annotated with.
+fn map_static_pod(pod: &Pod, pod_id: Uuid) {
+    if let Some(static_id) = config_hash(pod).and_then(|hash| Uuid::parse_str(hash).ok()) {
+        if static_id != pod_id {
+            cgidmap::rename_pod(static_id, pod_id);
+        }
+    }
+}
+
 fn update_pod_handler(pod: &Pod) {
+    if on_other_node(pod, NODE_NAME.as_deref()) {
+        return;
+    }
     let Some(pod_id) = parse_uuid(pod) else {
         warn!("Failed to parse pod UUID");
         return;
     };
+    map_static_pod(pod, pod_id);
 
     if let Some((mut running, _)) = extract_container_ids(pod) {
         cgidmap::update(pod_id, &mut running);
@@ -48,6 +73,30 @@ fn delete_pod_handler(pod: &Pod) {
         warn!("Failed to parse pod UUID");
         return;
     };
+    map_static_pod(pod, pod_id);
     // When a pod is deleted, we remove all entries for that pod
     cgidmap::update(pod_id, &mut std::collections::HashSet::new());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::PodSpec;
+
+    #[test]
+    fn test_on_other_node() {
+        let pod = |node: Option<&str>| Pod {
+            spec: Some(PodSpec {
+                node_name: node.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(on_other_node(&pod(Some("node-b")), Some("node-a")));
+        assert!(!on_other_node(&pod(Some("node-a")), Some("node-a")));
+        // Pending pods are not scheduled yet, and all pods are kept without NODE_NAME
+        assert!(!on_other_node(&pod(None), Some("node-a")));
+        assert!(!on_other_node(&pod(Some("node-b")), None));
+    }
+}
@@ -1,24 +1,46 @@
 use crate::cgidmap::add;
-use crate::rthooks::{
-    args::CreateContainerArg, register_callbacks_at_init, Callbacks, RtHookError,
-};
+use crate::cgtracker;
+use crate::rthooks::{args::CreateContainerArg, register_hook_at_init, RtHookError, RuntimeHook};
+use std::sync::Arc;
 use tracing::*;
 use uuid::Uuid;
 
-pub fn register_callback() {
-    register_callbacks_at_init(Callbacks {
-        create_container: Box::new(create_container_hook),
-    });
+struct CgidmapHook;
+
+#[tonic::async_trait]
+impl RuntimeHook for CgidmapHook {
+    fn name(&self) -> &str {
+        "cgidmap"
+    }
+
+    async fn create_container(&self, arg: &CreateContainerArg) -> Result<(), RtHookError> {
+        create_container_hook(arg).await
+    }
+}
+
+pub fn register_hook() {
+    register_hook_at_init(Arc::new(CgidmapHook));
 }
 
-fn create_container_hook(arg: &mut CreateContainerArg) -> Result<(), RtHookError> {
+async fn create_container_hook(arg: &CreateContainerArg) -> Result<(), RtHookError> {
     debug!("cgidmap::create_container_hook called");
     // TODO: support option
     // if !option::Config::enable_cg_idmap() {
     //     return Ok(());
     // }
 
-    let pod_id_str = arg.pod_id();
+    // The informer knows static pods by the uid of their mirror pod
+    let pod_id_str = if arg.is_static_pod() {
+        match arg.pod().await {
+            Ok(pod) => pod.metadata.uid.clone().unwrap_or_else(|| arg.pod_id()),
+            Err(e) => {
+                warn!("failed to find mirror pod, using the static pod uid: {}", e);
+                arg.pod_id()
+            }
+        }
+    } else {
+        arg.pod_id()
+    };
 
     let pod_id = match Uuid::parse_str(&pod_id_str) {
         Ok(id) => id,
@@ -44,17 +66,19 @@ fn create_container_hook(arg: &mut CreateContainerArg) -> Result<(), RtHookError
         }
     };
 
-    // TODO: cgtracker
-    // match arg.host_cgroup_path() {
-    //     Ok(cg_path) => {
-    //         if let Err(err) = cgtracker::add_cgroup_tracker_path(&cg_path) {
-    //             warn!("failed to add path to cgroup tracker");
-    //         }
-    //     }
-    //     Err(err) => {
-    //         warn!("could not retrieve host cgroup path, will not add path to cgroup tracker");
-    //     }
-    // }
+    match arg.host_cgroup_path() {
+        Ok(cg_path) => {
+            if let Err(e) = cgtracker::add_cgroup_tracker_path(&cg_path) {
+                warn!("failed to add path to cgroup tracker: {}", e);
+            }
+        }
+        Err(e) => {
+            warn!(
+                "could not retrieve host cgroup path, will not add path to cgroup tracker: {}",
+                e
+            );
+        }
+    }
 
     add(pod_id, container_id, cg_id);
     Ok(())
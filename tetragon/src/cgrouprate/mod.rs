@@ -0,0 +1,245 @@
+use crate::api::{get_events_response::Event, ProcessThrottle, ThrottleType};
+use crate::cgroups::cgroup_name_from_c_str;
+use aya::maps::{Array, HashMap, MapData};
+use aya::Ebpf;
+use std::collections::{HashMap as StdHashMap, HashSet};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tetragon_common::rate::{CgroupRateKey, CgroupRateOptions, CgroupRateValue, MsgThrottle};
+use tracing::*;
+
+const CGROUP_RATE_MAP: &str = "CGROUP_RATE_MAP";
+const CGROUP_RATE_CLEAR_MAP: &str = "CGROUP_RATE_CLEAR_MAP";
+const CGROUP_RATE_OPTIONS_MAP: &str = "CGROUP_RATE_OPTIONS_MAP";
+
+// The rates of the cgroups, only written by BPF, and the throttles that BPF
+// clears on the next execve of their cgroup
+pub struct CgroupRateMaps {
+    rates: HashMap<MapData, CgroupRateKey, CgroupRateValue>,
+    clears: HashMap<MapData, CgroupRateKey, u64>,
+}
+
+// Max execve events per interval for each cgroup, like CGROUP_RATE=1000,1s.
+// Exec events of a cgroup over the budget are dropped until its rate goes down.
+pub static CGROUP_RATE: LazyLock<Option<CgroupRateOptions>> = LazyLock::new(|| {
+    let s = std::env::var("CGROUP_RATE").ok()?;
+    let opts = parse_cgroup_rate(&s);
+    if opts.is_none() {
+        warn!("Invalid CGROUP_RATE: {}, the rate limit is disabled", s);
+    }
+    opts
+});
+
+// Names of the throttled cgroups, keyed by cgroup id
+static THROTTLED: LazyLock<Mutex<StdHashMap<u64, String>>> =
+    LazyLock::new(|| Mutex::new(StdHashMap::new()));
+
+fn parse_cgroup_rate(s: &str) -> Option<CgroupRateOptions> {
+    let (events, interval) = s.split_once(',')?;
+    let events = events.trim().parse::<u64>().ok()?;
+    let interval = parse_interval(interval.trim())?;
+    if events == 0 || interval.is_zero() {
+        return None;
+    }
+
+    Some(CgroupRateOptions {
+        events,
+        interval: interval.as_nanos() as u64,
+    })
+}
+
+fn parse_interval(s: &str) -> Option<Duration> {
+    let (value, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit())?);
+    let value = value.parse::<u64>().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(value)),
+        "s" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_secs(value * 60)),
+        _ => None,
+    }
+}
+
+// Configures the rate limit in BPF and returns the maps holding the per cgroup
+// rates, or None when CGROUP_RATE is not set.
+pub fn init(bpf: &mut Ebpf) -> anyhow::Result<Option<CgroupRateMaps>> {
+    let Some(opts) = *CGROUP_RATE else {
+        return Ok(None);
+    };
+
+    let mut options: Array<_, CgroupRateOptions> =
+        Array::try_from(bpf.map_mut(CGROUP_RATE_OPTIONS_MAP).unwrap())?;
+    options.set(0, opts, 0)?;
+    info!(
+        "cgroup rate limit: {} events per {}ns",
+        opts.events, opts.interval
+    );
+
+    Ok(Some(CgroupRateMaps {
+        rates: HashMap::try_from(bpf.take_map(CGROUP_RATE_MAP).unwrap())?,
+        clears: HashMap::try_from(bpf.take_map(CGROUP_RATE_CLEAR_MAP).unwrap())?,
+    }))
+}
+
+fn throttle_event(r#type: ThrottleType, cgroup: String) -> Event {
+    Event::ProcessThrottle(ProcessThrottle {
+        r#type: r#type.into(),
+        cgroup,
+    })
+}
+
+// Handles MsgOpThrottle, sent by BPF when a cgroup starts being throttled.
+pub fn throttle_start(msg: &MsgThrottle) -> Event {
+    let kube = msg.kube;
+    let mut cgroup = cgroup_name_from_c_str(&kube.docker_id);
+    if cgroup.is_empty() {
+        cgroup = kube.cgrpid.to_string();
+    }
+
+    THROTTLED
+        .lock()
+        .unwrap()
+        .insert(kube.cgrpid, cgroup.clone());
+    throttle_event(ThrottleType::ThrottleStart, cgroup)
+}
+
+fn ktime_now() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Same clock as bpf_ktime_get_ns()
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+// Whether a throttled cgroup's rate went below the limit
+fn throttle_ended(mut val: CgroupRateValue, opts: &CgroupRateOptions, time: u64) -> bool {
+    if val.throttled == 0 {
+        return false;
+    }
+
+    val.slide(time, opts.interval);
+    val.rate(time, opts.interval) <= opts.events
+}
+
+fn throttle_stop(id: u64, tx: &tokio::sync::broadcast::Sender<Event>) {
+    let cgroup = THROTTLED
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .unwrap_or_else(|| id.to_string());
+    let _ = tx.send(throttle_event(ThrottleType::ThrottleStop, cgroup));
+}
+
+fn process_cgroups(
+    maps: &mut CgroupRateMaps,
+    opts: &CgroupRateOptions,
+    tx: &tokio::sync::broadcast::Sender<Event>,
+) {
+    let time = ktime_now();
+    let mut present = HashSet::new();
+    let mut ended = Vec::new();
+    for (key, val) in maps.rates.iter().filter_map(|res| res.ok()) {
+        present.insert(key.id);
+        // Skips the throttles whose clear was asked and not applied yet
+        if throttle_ended(val, opts, time) && maps.clears.get(&key, 0).ok() != Some(val.throttled) {
+            ended.push((key, val.throttled));
+        }
+    }
+
+    // Cleared by BPF, which also updates the counts in the meantime
+    for (key, throttled) in ended {
+        if let Err(e) = maps.clears.insert(key, throttled, 0) {
+            warn!("Failed to clear throttle of cgroup {}: {}", key.id, e);
+            continue;
+        }
+        throttle_stop(key.id, tx);
+    }
+
+    // Throttled cgroups evicted from the LRU map are not throttled anymore.
+    // The iteration may miss entries updated meanwhile, so they are looked up.
+    let evicted: Vec<u64> = THROTTLED
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|id| !present.contains(*id))
+        .copied()
+        .collect();
+    for id in evicted {
+        if maps.rates.get(&CgroupRateKey { id }, 0).is_err() {
+            throttle_stop(id, tx);
+        }
+    }
+}
+
+// Periodically looks for throttled cgroups whose rate went down and resumes
+// their exec events.
+pub async fn run(
+    maps: Option<CgroupRateMaps>,
+    tx: tokio::sync::broadcast::Sender<Event>,
+    stop: impl std::future::Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let (Some(mut maps), Some(opts)) = (maps, *CGROUP_RATE) else {
+        stop.await;
+        return Ok(());
+    };
+
+    let mut ticker = tokio::time::interval(Duration::from_nanos(opts.interval));
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => process_cgroups(&mut maps, &opts, &tx),
+            _ = &mut stop => break,
+        }
+    }
+
+    info!("cgroup rate terminated");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_parse_cgroup_rate() {
+        let opts = parse_cgroup_rate("1000,1s").unwrap();
+        assert_eq!((opts.events, opts.interval), (1000, SEC));
+
+        let opts = parse_cgroup_rate("10, 500ms").unwrap();
+        assert_eq!((opts.events, opts.interval), (10, SEC / 2));
+
+        assert!(parse_cgroup_rate("1000").is_none());
+        assert!(parse_cgroup_rate("0,1s").is_none());
+        assert!(parse_cgroup_rate("1000,1h").is_none());
+    }
+
+    #[test]
+    fn test_cgroup_rate_throttle() {
+        let opts = CgroupRateOptions {
+            events: 10,
+            interval: SEC,
+        };
+
+        // 20 events in the first interval
+        let mut val = CgroupRateValue::new(SEC, opts.interval);
+        for i in 0..20 {
+            val.slide(SEC + i, opts.interval);
+            val.curr += 1;
+        }
+        assert_eq!(val.rate(SEC + 20, opts.interval), 20);
+        val.throttled = SEC + 20;
+
+        // Half of the previous interval still counts
+        assert!(!throttle_ended(val, &opts, 2 * SEC + SEC / 4));
+        assert!(throttle_ended(val, &opts, 2 * SEC + SEC / 2 + 1));
+
+        // Nothing happened for more than two intervals
+        assert!(throttle_ended(val, &opts, 10 * SEC));
+
+        val.throttled = 0;
+        assert!(!throttle_ended(val, &opts, 10 * SEC));
+    }
+}
@@ -1,13 +1,16 @@
 #![allow(dead_code)]
 use super::CgroupModeCode;
-use crate::cgroups::{DeploymentCode, DeploymentEnv};
+use crate::cgroups::{DeploymentCode, DeploymentEnv, CGROUP_SUBSYS_COUNT};
+use std::ffi::CString;
 use std::fs::File;
 use std::io::Error;
 use std::mem;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::AsRawFd;
 use std::path::Path;
 
 use std::sync::{LazyLock, OnceLock};
+use tetragon_common::conf::{TetragonConf, CGROUP2_SUPER_MAGIC, CGROUP_SUPER_MAGIC};
 use tracing::*;
 
 #[derive(Debug, Clone)]
@@ -21,6 +24,11 @@ pub struct CgroupController {
 // Path where default cgroupfs is mounted
 const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
 
+// Inodes of the initial cgroup and pid namespaces, from
+// include/linux/proc_ns.h
+const PROC_CGROUP_INIT_INO: u64 = 0xEFFFFFFB;
+const PROC_PID_INIT_INO: u64 = 0xEFFFFFFC;
+
 /* Cgroup controllers that we are interested in
  * are usually the ones that are setup by systemd
  * or other init programs.
@@ -51,7 +59,10 @@ pub(crate) static CGROUP_CONTROLLERS: LazyLock<Vec<CgroupController>> = LazyLock
     ]
 });
 
-// TODO: cgroupv2Hierarchy = "0::"
+// Prefix of the cgroup v2 entry in /proc/<pid>/cgroup
+const CGROUP2_HIERARCHY: &str = "0::";
+
+const TMPFS_MAGIC: u64 = 0x01021994;
 
 /* Ordered from nested to top cgroup parents
  * For k8s we check also config k8s flags.
@@ -96,39 +107,122 @@ static DEPLOYMENT_MODE: OnceLock<DeploymentCode> = OnceLock::new();
 
 static CGROUP_MODE: OnceLock<CgroupModeCode> = OnceLock::new();
 
-// detectCgroupFSOnce sync.Once
-// cgroupFSPath       string
-// cgroupFSMagic      uint64
-
 static CGRP_MIGRATION_PATH: OnceLock<String> = OnceLock::new();
 
-fn _detect_deployment_mode() -> anyhow::Result<DeploymentCode> {
-    let mode = get_deployment_mode();
-    if mode != DeploymentCode::Unknown {
-        return Ok(mode);
+fn statfs_magic(path: &str) -> Result<u64, Error> {
+    let cpath = CString::new(path)?;
+    let mut st: libc::statfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statfs(cpath.as_ptr(), &mut st) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(st.f_type as u64)
+}
+
+fn detect_cgroup_mode_at(cgroupfs: &str) -> anyhow::Result<CgroupModeCode> {
+    let magic = statfs_magic(cgroupfs)?;
+    if magic == CGROUP2_SUPER_MAGIC {
+        return Ok(CgroupModeCode::Unified);
     }
 
-    // TODO
-    // Parse own cgroup paths and detect the deployment mode
-    // let pid = std::process::id();
+    // Legacy and hybrid hierarchies are mounted on a tmpfs, with the cgroup v2
+    // one under unified in the hybrid case
+    if magic == TMPFS_MAGIC {
+        let unified = Path::new(cgroupfs).join("unified");
+        return match statfs_magic(&unified.to_string_lossy()) {
+            Ok(magic) if magic == CGROUP2_SUPER_MAGIC => Ok(CgroupModeCode::Hybrid),
+            _ => Ok(CgroupModeCode::Legacy),
+        };
+    }
+
+    Err(anyhow::anyhow!(
+        "wrong type '{:#x}' for cgroupfs '{}'",
+        magic,
+        cgroupfs
+    ))
+}
+
+pub fn detect_cgroup_mode() -> CgroupModeCode {
+    *CGROUP_MODE.get_or_init(|| match detect_cgroup_mode_at(DEFAULT_CGROUP_ROOT) {
+        Ok(mode) => {
+            info!("Cgroup mode detection succeeded: {:?}", mode);
+            mode
+        }
+        Err(e) => {
+            warn!("Failed to detect cgroup mode: {}", e);
+            CgroupModeCode::Undefined
+        }
+    })
+}
 
-    // find_migration_path(pid)?;
-    // Ok(get_deployment_mode())
+// Returns the cgroup path of the process from the content of
+// /proc/<pid>/cgroup. On cgroup v1 the hierarchy of the first controller of
+// CGROUP_CONTROLLERS is used.
+fn parse_migration_path(content: &str, mode: CgroupModeCode) -> Option<String> {
+    if mode == CgroupModeCode::Unified {
+        return content
+            .lines()
+            .find_map(|line| line.strip_prefix(CGROUP2_HIERARCHY))
+            .map(str::to_string);
+    }
+
+    CGROUP_CONTROLLERS.iter().find_map(|controller| {
+        content.lines().find_map(|line| {
+            // hierarchy-ID:controller-list:cgroup-path
+            let mut fields = line.splitn(3, ':');
+            let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+            controllers
+                .split(',')
+                .any(|c| c == controller.name)
+                .then(|| path.to_string())
+        })
+    })
+}
+
+fn deployment_mode_from_path(path: &str) -> DeploymentCode {
+    DEPLOYMENTS
+        .iter()
+        .find(|d| {
+            d.str.as_ref().is_some_and(|s| path.contains(s.as_str()))
+                || d.ends_with
+                    .as_ref()
+                    .is_some_and(|s| path.ends_with(s.as_str()))
+        })
+        .map_or(DeploymentCode::Unknown, |d| d.id)
+}
+
+// Returns the cgroup path of a process, like /system.slice/nginx.service
+pub fn process_cgroup_path(pid: u32) -> anyhow::Result<String> {
+    let mode = detect_cgroup_mode();
+    if mode == CgroupModeCode::Undefined {
+        return Err(anyhow::anyhow!("cgroup mode is not detected"));
+    }
+
+    let content = std::fs::read_to_string(format!("/proc/{}/cgroup", pid))?;
+    parse_migration_path(&content, mode)
+        .ok_or_else(|| anyhow::anyhow!("no cgroup path found in /proc/{}/cgroup", pid))
+}
+
+fn find_migration_path(pid: u32) -> anyhow::Result<String> {
+    let path = process_cgroup_path(pid)?;
+    Ok(CGRP_MIGRATION_PATH.get_or_init(|| path).clone())
+}
 
-    Ok(DeploymentCode::Unknown)
+fn detect_deployment_mode_from_cgroup() -> anyhow::Result<DeploymentCode> {
+    // Parse own cgroup paths and detect the deployment mode
+    let path = find_migration_path(std::process::id())?;
+    debug!("Cgroup migration path: {}", path);
+    Ok(deployment_mode_from_path(&path))
 }
 
 pub fn detect_deployment_mode() -> anyhow::Result<DeploymentCode> {
-    DEPLOYMENT_MODE.get_or_init(|| match detect_deployment_mode() {
+    let mode = *DEPLOYMENT_MODE.get_or_init(|| match detect_deployment_mode_from_cgroup() {
         Ok(mode) => mode,
         Err(e) => {
-            // TODO: return an error using get_or_try_init
             warn!("Failed to detect deployment mode: {}", e);
             DeploymentCode::Unknown
         }
     });
 
-    let mode = get_deployment_mode();
     if mode == DeploymentCode::Unknown {
         warn!("Deployment mode detection failed");
     } else {
@@ -142,9 +236,115 @@ pub fn get_deployment_mode() -> DeploymentCode {
     *DEPLOYMENT_MODE.get().unwrap_or(&DeploymentCode::Unknown)
 }
 
+pub fn get_cgroup_mode() -> CgroupModeCode {
+    *CGROUP_MODE.get().unwrap_or(&CgroupModeCode::Undefined)
+}
+
+// Parses /proc/cgroups, where controllers are listed in the order of their
+// index in css_set->subsys.
+fn parse_proc_cgroups(content: &str) -> Vec<CgroupController> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [name, hierarchy, _num_cgroups, enabled] = fields[..] else {
+                return None;
+            };
+            Some(CgroupController {
+                id: hierarchy.parse().ok()?,
+                idx: idx as u32,
+                name: name.to_string(),
+                active: enabled == "1",
+            })
+        })
+        .collect()
+}
+
+// Returns the index of the first cgroup v1 controller of CGROUP_CONTROLLERS
+// which is active and mounted.
+fn cgrpv1_subsys_idx(controllers: &[CgroupController]) -> Option<u32> {
+    CGROUP_CONTROLLERS.iter().find_map(|wanted| {
+        controllers
+            .iter()
+            .find(|c| c.name == wanted.name && c.active && c.id != 0)
+            .map(|c| c.idx)
+            .filter(|&idx| (idx as usize) < CGROUP_SUBSYS_COUNT)
+    })
+}
+
+// Configuration of the BPF programs that depends on the cgroup setup. Detects
+// the cgroup mode itself, so it never configures BPF with an undefined mode.
+pub fn tg_conf() -> TetragonConf {
+    let mut conf = TetragonConf::default();
+
+    match detect_cgroup_mode() {
+        CgroupModeCode::Unified => conf.cgrp_fs_magic = CGROUP2_SUPER_MAGIC,
+        CgroupModeCode::Legacy | CgroupModeCode::Hybrid => {
+            conf.cgrp_fs_magic = CGROUP_SUPER_MAGIC;
+            let controllers = std::fs::read_to_string("/proc/cgroups")
+                .map(|content| parse_proc_cgroups(&content))
+                .unwrap_or_default();
+            match cgrpv1_subsys_idx(&controllers) {
+                Some(idx) => conf.tg_cgrpv1_subsys_idx = idx,
+                None => warn!("No usable cgroup v1 controller found in /proc/cgroups"),
+            }
+        }
+        CgroupModeCode::Undefined => {}
+    }
+
+    conf
+}
+
+fn is_cgroup_root(path: &str) -> bool {
+    match statfs_magic(path) {
+        Ok(CGROUP2_SUPER_MAGIC) => true,
+        // cgroup v1 hierarchies are mounted below a tmpfs
+        Ok(TMPFS_MAGIC) => CGROUP_CONTROLLERS
+            .iter()
+            .any(|c| Path::new(path).join(&c.name).exists()),
+        _ => false,
+    }
+}
+
+fn ns_inode(pid: &str, ns: &str) -> Option<u64> {
+    std::fs::metadata(format!("/proc/{}/ns/{}", pid, ns))
+        .map(|m| m.ino())
+        .ok()
+}
+
+// Returns the path of the host cgroup root. When the agent runs in a container
+// with a private cgroup namespace, /sys/fs/cgroup only shows the container
+// cgroup, so the mount of the init process of the host pid namespace is used.
+// Without hostPID, /proc/1 is the init of the agent container instead.
 pub fn host_cgroup_root() -> Result<String, std::io::Error> {
-    // TODO: implement
-    Ok(DEFAULT_CGROUP_ROOT.to_string())
+    static HOST_CGROUP_ROOT: OnceLock<String> = OnceLock::new();
+
+    if let Some(root) = HOST_CGROUP_ROOT.get() {
+        return Ok(root.clone());
+    }
+
+    let mut candidates = vec![DEFAULT_CGROUP_ROOT.to_string()];
+    if ns_inode("self", "pid") == Some(PROC_PID_INIT_INO) {
+        candidates.insert(0, format!("/proc/1/root{}", DEFAULT_CGROUP_ROOT));
+    } else if ns_inode("self", "cgroup") != Some(PROC_CGROUP_INIT_INO) {
+        warn!("Running without hostPID in a private cgroup namespace, container cgroups will not be found");
+    } else {
+        warn!(
+            "Running without hostPID, using {} as the host cgroup root",
+            DEFAULT_CGROUP_ROOT
+        );
+    }
+    let Some(root) = candidates.into_iter().find(|path| is_cgroup_root(path)) else {
+        return Err(Error::new(
+            std::io::ErrorKind::NotFound,
+            "failed to find the host cgroup root",
+        ));
+    };
+
+    info!("Host cgroup root: {}", root);
+    Ok(HOST_CGROUP_ROOT.get_or_init(|| root).clone())
 }
 
 #[repr(C)]
@@ -152,7 +352,7 @@ struct FileHandle {
     id: u64,
 }
 
-fn get_cgroup_id_from_path(cgroup_path: &str) -> Result<u64, Error> {
+pub fn get_cgroup_id_from_path(cgroup_path: &str) -> Result<u64, Error> {
     debug!("get_cgroup_id_from_path: {}", cgroup_path);
     let path = Path::new(cgroup_path);
     let file = File::open(path)?;
@@ -235,3 +435,77 @@ pub fn get_cgroup_id_from_sub_cgroup(p: &str) -> Result<u64, std::io::Error> {
     debug!("get_cgroup_id_from_sub_cgroup: res: {}", path);
     get_cgroup_id_from_path(&path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_CGROUPS: &str = "#subsys_name\thierarchy\tnum_cgroups\tenabled
+cpuset\t2\t4\t1
+cpu\t3\t60\t1
+cpuacct\t3\t60\t1
+blkio\t4\t60\t1
+memory\t0\t60\t0
+devices\t5\t60\t1
+freezer\t6\t4\t1
+net_cls\t7\t4\t1
+perf_event\t8\t4\t1
+net_prio\t7\t4\t1
+hugetlb\t9\t4\t1
+pids\t10\t62\t1
+";
+
+    #[test]
+    fn test_parse_migration_path() {
+        let content = "0::/kubepods.slice/kubepods-besteffort.slice/cri-containerd-abc.scope\n";
+        assert_eq!(
+            parse_migration_path(content, CgroupModeCode::Unified).unwrap(),
+            "/kubepods.slice/kubepods-besteffort.slice/cri-containerd-abc.scope"
+        );
+
+        let content = "12:pids:/system.slice/tetragon.service
+11:cpu,cpuacct:/system.slice/tetragon.service
+5:memory:/system.slice/tetragon.service
+0::/system.slice/tetragon.service
+";
+        assert_eq!(
+            parse_migration_path(content, CgroupModeCode::Legacy).unwrap(),
+            "/system.slice/tetragon.service"
+        );
+        assert!(parse_migration_path("1:name=systemd:/\n", CgroupModeCode::Hybrid).is_none());
+    }
+
+    #[test]
+    fn test_deployment_mode_from_path() {
+        assert_eq!(
+            deployment_mode_from_path("/kubepods/besteffort/pod1234/abcd"),
+            DeploymentCode::Kubernetes
+        );
+        assert_eq!(
+            deployment_mode_from_path("/system.slice/docker-abcd.scope"),
+            DeploymentCode::Container
+        );
+        assert_eq!(
+            deployment_mode_from_path("/system.slice/tetragon.service"),
+            DeploymentCode::SystemdService
+        );
+        assert_eq!(
+            deployment_mode_from_path("/user.slice/user-1000.slice/session-2.scope"),
+            DeploymentCode::SystemdUserSession
+        );
+        assert_eq!(deployment_mode_from_path("/"), DeploymentCode::Unknown);
+    }
+
+    #[test]
+    fn test_cgrpv1_subsys_idx() {
+        let controllers = parse_proc_cgroups(PROC_CGROUPS);
+        assert_eq!(controllers.len(), 12);
+
+        // memory is disabled, so pids is used
+        assert_eq!(cgrpv1_subsys_idx(&controllers), Some(11));
+
+        // cgroup v2 only, no controller is mounted on a v1 hierarchy
+        let controllers = parse_proc_cgroups("cpuset\t0\t1\t1\nmemory\t0\t1\t1\n");
+        assert_eq!(cgrpv1_subsys_idx(&controllers), None);
+    }
+}
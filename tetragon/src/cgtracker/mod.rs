@@ -0,0 +1,75 @@
+use crate::cgroups::linux::get_cgroup_id_from_path;
+use aya::maps::{HashMap, MapData};
+use aya::Ebpf;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing::*;
+
+const CGTRACKER_MAP: &str = "TG_CGTRACKER_MAP";
+
+// Maps cgroup ids to the id of the tracking cgroup. BPF keeps it up to date
+// when cgroups are created or removed below a tracking cgroup.
+static CGTRACKER: OnceLock<Mutex<HashMap<MapData, u64, u64>>> = OnceLock::new();
+
+pub fn init(bpf: &mut Ebpf) -> anyhow::Result<()> {
+    let map = HashMap::try_from(bpf.take_map(CGTRACKER_MAP).unwrap())?;
+    if CGTRACKER.set(Mutex::new(map)).is_err() {
+        return Err(anyhow::anyhow!("cgroup tracker is already initialized"));
+    }
+    Ok(())
+}
+
+// Returns the ids of the cgroup at path and of all the cgroups below it.
+fn cgroup_ids(path: &Path) -> anyhow::Result<Vec<u64>> {
+    let mut ids = vec![get_cgroup_id_from_path(&path.to_string_lossy())?];
+
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            ids.extend(cgroup_ids(&entry.path())?);
+        }
+    }
+    Ok(ids)
+}
+
+// Tracks the cgroup at path: it and the cgroups below it, including the ones
+// created later, are mapped to its cgroup id.
+pub fn add_cgroup_tracker_path(path: &str) -> anyhow::Result<()> {
+    let Some(map) = CGTRACKER.get() else {
+        return Err(anyhow::anyhow!("cgroup tracker is not initialized"));
+    };
+
+    let ids = cgroup_ids(Path::new(path))?;
+    let tracker_id = ids[0];
+
+    let mut map = map.lock().unwrap();
+    for id in ids {
+        map.insert(id, tracker_id, 0)?;
+    }
+    debug!("cgtracker: tracking {} with id {}", path, tracker_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cgroup_ids() {
+        let root = std::env::temp_dir().join(format!("cgtracker-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::create_dir_all(root.join("c")).unwrap();
+        std::fs::write(root.join("cgroup.procs"), "").unwrap();
+
+        let root_id = get_cgroup_id_from_path(&root.to_string_lossy()).unwrap();
+        let mut ids = cgroup_ids(&root).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        // The tracking cgroup comes first
+        assert_eq!(ids.len(), 4);
+        assert_eq!(ids[0], root_id);
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+    }
+}
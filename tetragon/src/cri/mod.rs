@@ -0,0 +1,96 @@
+use crate::cri_api::runtime_service_client::RuntimeServiceClient;
+use crate::cri_api::ContainerStatusRequest;
+use serde::Deserialize;
+use std::path::Path;
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+use tracing::*;
+
+pub type CriClient = RuntimeServiceClient<Channel>;
+
+// Used when CRI_ENDPOINT is not set, in this order
+const DEFAULT_ENDPOINTS: [&str; 3] = [
+    "/run/containerd/containerd.sock",
+    "/run/crio/crio.sock",
+    "/var/run/cri-dockerd.sock",
+];
+
+// Returns the path of the CRI socket, from CRI_ENDPOINT like
+// unix:///run/containerd/containerd.sock or the first default one found.
+pub fn endpoint() -> Option<String> {
+    if let Ok(endpoint) = std::env::var("CRI_ENDPOINT") {
+        return Some(
+            endpoint
+                .strip_prefix("unix://")
+                .unwrap_or(&endpoint)
+                .to_string(),
+        );
+    }
+
+    DEFAULT_ENDPOINTS
+        .iter()
+        .find(|path| Path::new(path).exists())
+        .map(|path| path.to_string())
+}
+
+pub async fn connect(socket: &str) -> anyhow::Result<CriClient> {
+    let socket = socket.to_string();
+    // The uri is ignored, the connector always dials the unix socket
+    let channel = Endpoint::try_from("http://[::]:50051")?
+        .connect_with_connector(service_fn(move |_: Uri| {
+            UnixStream::connect(socket.clone())
+        }))
+        .await?;
+
+    debug!("connected to CRI runtime service");
+    Ok(RuntimeServiceClient::new(channel))
+}
+
+// Only the fields we need from the verbose info of containerd and CRI-O
+#[derive(Deserialize)]
+struct ContainerInfo {
+    #[serde(rename = "runtimeSpec")]
+    runtime_spec: RuntimeSpec,
+}
+
+#[derive(Deserialize)]
+struct RuntimeSpec {
+    linux: Linux,
+}
+
+#[derive(Deserialize)]
+struct Linux {
+    #[serde(rename = "cgroupsPath", default)]
+    cgroups_path: String,
+}
+
+// Returns the cgroupsPath of the OCI spec of the container, as found in the
+// verbose info of ContainerStatus.
+pub async fn cgroup_path(client: &mut CriClient, container_id: &str) -> anyhow::Result<String> {
+    let res = client
+        .container_status(ContainerStatusRequest {
+            container_id: container_id.to_string(),
+            verbose: true,
+        })
+        .await?
+        .into_inner();
+
+    let Some(info) = res.info.get("info") else {
+        return Err(anyhow::anyhow!(
+            "no info in the status of container {}",
+            container_id
+        ));
+    };
+
+    let info: ContainerInfo = serde_json::from_str(info)?;
+    let cgroups_path = info.runtime_spec.linux.cgroups_path;
+    if cgroups_path.is_empty() {
+        return Err(anyhow::anyhow!(
+            "empty cgroupsPath for container {}",
+            container_id
+        ));
+    }
+
+    Ok(cgroups_path)
+}
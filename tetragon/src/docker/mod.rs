@@ -0,0 +1,167 @@
+use crate::api::{Container, Image};
+use anyhow::Context as _;
+use prost_types::Timestamp;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+// Used when DOCKER_HOST is not set, in this order. Podman serves the same API
+// on its socket.
+const DEFAULT_ENDPOINTS: [&str; 3] = [
+    "/var/run/docker.sock",
+    "/run/podman/podman.sock",
+    "/run/user/0/podman/podman.sock",
+];
+
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Returns the path of the Docker or Podman socket, from DOCKER_HOST like
+// unix:///var/run/docker.sock or the first default one found.
+pub fn endpoint() -> Option<String> {
+    if let Ok(host) = std::env::var("DOCKER_HOST") {
+        // Only local sockets are supported
+        return host.strip_prefix("unix://").map(|path| path.to_string());
+    }
+
+    DEFAULT_ENDPOINTS
+        .iter()
+        .find(|path| Path::new(path).exists())
+        .map(|path| path.to_string())
+}
+
+// Only the fields we need from the container inspect API
+#[derive(Debug, Deserialize)]
+struct ContainerJson {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "Name", default)]
+    name: String,
+    #[serde(rename = "Image", default)]
+    image: String,
+    #[serde(rename = "Config", default)]
+    config: Option<Config>,
+    #[serde(rename = "State", default)]
+    state: Option<State>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    #[serde(rename = "Image", default)]
+    image: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct State {
+    #[serde(rename = "StartedAt", default)]
+    started_at: String,
+}
+
+fn container_from_json(json: ContainerJson) -> Container {
+    let start_time = json
+        .state
+        .and_then(|state| chrono::DateTime::parse_from_rfc3339(&state.started_at).ok())
+        .map(|time| Timestamp {
+            seconds: time.timestamp(),
+            nanos: time.timestamp_subsec_nanos() as i32,
+        });
+
+    Container {
+        id: json.id,
+        // Docker prefixes the names with a slash
+        name: json.name.trim_start_matches('/').to_string(),
+        image: Some(Image {
+            id: json.image,
+            name: json.config.map(|config| config.image).unwrap_or_default(),
+        }),
+        start_time,
+        ..Default::default()
+    }
+}
+
+// Splits an HTTP response into its status code and body
+fn parse_response(response: &[u8]) -> anyhow::Result<(u16, &[u8])> {
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("truncated HTTP response")?;
+    let head = std::str::from_utf8(&response[..end])?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .with_context(|| format!("invalid HTTP status line: {}", head))?;
+    Ok((status, &response[end + 4..]))
+}
+
+async fn get(socket: &str, path: &str) -> anyhow::Result<(u16, Vec<u8>)> {
+    let mut stream = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("failed to connect to {}", socket))?;
+    // HTTP/1.0 so that the body is neither chunked nor kept alive
+    let request = format!("GET {} HTTP/1.0\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let (status, body) = parse_response(&response)?;
+    Ok((status, body.to_vec()))
+}
+
+// Returns the container from the inspect API, or None when the runtime does
+// not know it
+pub async fn inspect(socket: &str, container_id: &str) -> anyhow::Result<Option<Container>> {
+    let path = format!("/containers/{}/json", container_id);
+    let (status, body) = tokio::time::timeout(REQUEST_TIMEOUT, get(socket, &path))
+        .await
+        .context("container inspect timed out")??;
+
+    match status {
+        200 => {
+            let json: ContainerJson = serde_json::from_slice(&body)?;
+            Ok(Some(container_from_json(json)))
+        }
+        404 => Ok(None),
+        _ => Err(anyhow::anyhow!(
+            "container inspect failed with status {}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_from_response() {
+        let response = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\
+            \"Id\": \"5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147\",\
+            \"Name\": \"/web\",\
+            \"Image\": \"sha256:e4720093a3c1381245b53a5a51b417963b3c4472d3f47fc301930a4f3b17666a\",\
+            \"Config\": {\"Image\": \"nginx:1.27\"},\
+            \"State\": {\"Running\": true, \"StartedAt\": \"2024-11-14T22:13:20.5Z\"}\
+        }";
+
+        let (status, body) = parse_response(response).unwrap();
+        assert_eq!(status, 200);
+        let container = container_from_json(serde_json::from_slice(body).unwrap());
+        assert_eq!(container.name, "web");
+        let image = container.image.unwrap();
+        assert_eq!(image.name, "nginx:1.27");
+        assert!(image.id.starts_with("sha256:"));
+        assert_eq!(
+            container.start_time,
+            Some(Timestamp {
+                seconds: 1731622400,
+                nanos: 500_000_000,
+            })
+        );
+
+        let (status, _) = parse_response(b"HTTP/1.1 404 Not Found\r\n\r\n").unwrap();
+        assert_eq!(status, 404);
+        assert!(parse_response(b"HTTP/1.0 200 OK\r\n").is_err());
+    }
+}
@@ -1,6 +1,8 @@
 pub mod bpf;
 pub mod cgidmap;
+pub mod cgrouprate;
 pub mod cgroups;
+pub mod cgtracker;
 pub mod process;
 pub mod reader;
 pub mod server;
@@ -9,8 +11,23 @@ pub mod api {
     #![allow(clippy::all)]
     tonic::include_proto!("tetragon");
 }
+pub mod cri_api {
+    #![allow(clippy::all)]
+    tonic::include_proto!("runtime.v1");
+}
+pub mod cri;
+pub mod docker;
+pub mod nri_api {
+    #![allow(clippy::all)]
+    tonic::include_proto!("nri.pkg.api.v1alpha1");
+}
+pub mod ttrpc_api {
+    #![allow(clippy::all)]
+    tonic::include_proto!("ttrpc");
+}
 pub mod ktime;
 pub mod metrics;
+pub mod nri;
 pub mod observer;
 pub mod podhelpers;
 pub mod rthooks;
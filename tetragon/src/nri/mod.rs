@@ -0,0 +1,435 @@
+// NRI plugin feeding the container lifecycle events of containerd and CRI-O
+// to the runtime hooks, without installing OCI hooks in the runtime:
+// https://github.com/containerd/nri
+#[cfg(test)]
+pub(crate) mod stub;
+pub mod ttrpc;
+
+use crate::api::{self, runtime_hook_request, RuntimeHookRequest};
+use crate::nri_api::{
+    ConfigureRequest, ConfigureResponse, Container, CreateContainerResponse, Empty, Event,
+    PodSandbox, RegisterPluginRequest, StateChangeEvent, StopContainerRequest,
+    StopContainerResponse, SynchronizeRequest, SynchronizeResponse,
+};
+use crate::rthooks;
+use crate::ttrpc_api::{Request, Status};
+use anyhow::Context as _;
+use prost::Message as _;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UnixStream;
+use tokio::sync::Mutex;
+use tracing::*;
+use ttrpc::{
+    Message, MuxReader, MuxWriter, CODE_INVALID_ARGUMENT, CODE_UNIMPLEMENTED, MESSAGE_TYPE_REQUEST,
+    MESSAGE_TYPE_RESPONSE, PLUGIN_SERVICE_CONN, RUNTIME_SERVICE_CONN,
+};
+
+const DEFAULT_SOCKET: &str = "/var/run/nri/nri.sock";
+const PLUGIN_NAME: &str = "tetragon";
+// Plugins are called in the order of their index, from 00 to 99
+const DEFAULT_PLUGIN_IDX: &str = "10";
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+// Used until the runtime sends its own in the configuration
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub(crate) const RUNTIME_SERVICE: &str = "nri.pkg.api.v1alpha1.Runtime";
+pub(crate) const PLUGIN_SERVICE: &str = "nri.pkg.api.v1alpha1.Plugin";
+
+// The cgroup of a container only exists once it is created, so the creation
+// hooks run on PostCreateContainer rather than on CreateContainer.
+const EVENTS: [Event; 4] = [
+    Event::PostCreateContainer,
+    Event::StartContainer,
+    Event::StopContainer,
+    Event::RemoveContainer,
+];
+
+fn event_mask(events: &[Event]) -> i32 {
+    events
+        .iter()
+        .fold(0, |mask, &event| mask | (1 << (event as i32 - 1)))
+}
+
+// Returns the path of the NRI socket, from NRI_SOCKET or the default one when it exists
+pub fn socket() -> Option<String> {
+    if let Ok(socket) = std::env::var("NRI_SOCKET") {
+        return Some(socket);
+    }
+    Path::new(DEFAULT_SOCKET)
+        .exists()
+        .then(|| DEFAULT_SOCKET.to_string())
+}
+
+fn plugin_idx() -> String {
+    std::env::var("NRI_PLUGIN_IDX").unwrap_or(DEFAULT_PLUGIN_IDX.to_string())
+}
+
+struct Plugin {
+    request_timeout_ms: AtomicU64,
+}
+
+impl Default for Plugin {
+    fn default() -> Self {
+        Self {
+            request_timeout_ms: AtomicU64::new(DEFAULT_REQUEST_TIMEOUT.as_millis() as u64),
+        }
+    }
+}
+
+impl Plugin {
+    async fn handle(&self, payload: &[u8]) -> Result<Vec<u8>, Status> {
+        let req = Request::decode(payload)
+            .map_err(|e| ttrpc::status(CODE_INVALID_ARGUMENT, e.to_string()))?;
+        if req.service != PLUGIN_SERVICE {
+            return Err(ttrpc::status(
+                CODE_UNIMPLEMENTED,
+                format!("unknown service {}", req.service),
+            ));
+        }
+        let invalid = |e: prost::DecodeError| ttrpc::status(CODE_INVALID_ARGUMENT, e.to_string());
+
+        match req.method.as_str() {
+            "Configure" => {
+                let config = ConfigureRequest::decode(req.payload.as_slice()).map_err(invalid)?;
+                info!(
+                    "nri: configured by {} {}",
+                    config.runtime_name, config.runtime_version
+                );
+                if config.request_timeout > 0 {
+                    self.request_timeout_ms
+                        .store(config.request_timeout as u64, Ordering::Relaxed);
+                }
+                Ok(ConfigureResponse {
+                    events: event_mask(&EVENTS),
+                }
+                .encode_to_vec())
+            }
+            // Existing containers are already mapped by the cgidmap bootstrap
+            "Synchronize" => {
+                let sync = SynchronizeRequest::decode(req.payload.as_slice()).map_err(invalid)?;
+                debug!("nri: synchronized {} containers", sync.containers.len());
+                Ok(SynchronizeResponse { more: false }.encode_to_vec())
+            }
+            "CreateContainer" => Ok(CreateContainerResponse {}.encode_to_vec()),
+            "StopContainer" => {
+                let stop = StopContainerRequest::decode(req.payload.as_slice()).map_err(invalid)?;
+                if let Some(event) = hook_event(
+                    Event::StopContainer,
+                    stop.pod.as_ref(),
+                    stop.container.as_ref(),
+                ) {
+                    self.run_hooks(event).await;
+                }
+                Ok(StopContainerResponse {}.encode_to_vec())
+            }
+            "StateChange" => {
+                let change = StateChangeEvent::decode(req.payload.as_slice()).map_err(invalid)?;
+                if let Some(event) = hook_event(
+                    change.event(),
+                    change.pod.as_ref(),
+                    change.container.as_ref(),
+                ) {
+                    self.run_hooks(event).await;
+                }
+                Ok(Empty {}.encode_to_vec())
+            }
+            "Shutdown" => {
+                info!("nri: shutdown by the runtime");
+                Ok(Empty {}.encode_to_vec())
+            }
+            method => Err(ttrpc::status(
+                CODE_UNIMPLEMENTED,
+                format!("unknown method {}", method),
+            )),
+        }
+    }
+
+    // The runtime drops the plugins that are too slow to answer, so the hooks
+    // keep running in the background past half the request timeout.
+    async fn run_hooks(&self, event: runtime_hook_request::Event) {
+        let timeout = Duration::from_millis(self.request_timeout_ms.load(Ordering::Relaxed)) / 2;
+        let request = RuntimeHookRequest { event: Some(event) };
+        let task = tokio::spawn(async move { rthooks::run_hooks(&request).await });
+
+        match tokio::time::timeout(timeout, task).await {
+            Ok(Ok(Ok(()))) => {}
+            Ok(Ok(Err(e))) => warn!("nri: runtime hooks failed: {}", e),
+            Ok(Err(e)) => warn!("nri: runtime hooks task failed: {}", e),
+            Err(_) => debug!("nri: runtime hooks still running after {:?}", timeout),
+        }
+    }
+}
+
+// Converts an NRI event to the event of the runtime hooks, if any
+fn hook_event(
+    event: Event,
+    pod: Option<&PodSandbox>,
+    container: Option<&Container>,
+) -> Option<runtime_hook_request::Event> {
+    let pod = pod.cloned().unwrap_or_default();
+    let container = container?;
+    let cgroups_path = container
+        .linux
+        .as_ref()
+        .map(|linux| linux.cgroups_path.clone())
+        .unwrap_or_default();
+    let container_id = container.id.clone();
+    let annotations = container.annotations.clone();
+
+    let event = match event {
+        Event::PostCreateContainer => {
+            runtime_hook_request::Event::CreateContainer(api::CreateContainer {
+                cgroups_path,
+                root_dir: String::new(),
+                container_name: container.name.clone(),
+                container_id,
+                pod_name: pod.name,
+                pod_uid: pod.uid,
+                pod_namespace: pod.namespace,
+                annotations,
+            })
+        }
+        Event::StartContainer => runtime_hook_request::Event::StartContainer(api::StartContainer {
+            cgroups_path,
+            container_id,
+            pod_uid: pod.uid,
+            annotations,
+            ..Default::default()
+        }),
+        Event::StopContainer => runtime_hook_request::Event::StopContainer(api::StopContainer {
+            cgroups_path,
+            container_id,
+            pod_uid: pod.uid,
+            annotations,
+            ..Default::default()
+        }),
+        Event::RemoveContainer => {
+            runtime_hook_request::Event::DeleteContainer(api::DeleteContainer {
+                cgroups_path,
+                container_id,
+                pod_uid: pod.uid,
+                annotations,
+                ..Default::default()
+            })
+        }
+        _ => return None,
+    };
+    Some(event)
+}
+
+// Registers the plugin and serves the requests of the runtime until it closes
+// the connection.
+async fn serve(socket: &Path) -> anyhow::Result<()> {
+    let stream = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("failed to connect to {}", socket.display()))?;
+    let (rd, wr) = stream.into_split();
+    let mut reader = MuxReader::new(rd);
+    let writer = Arc::new(Mutex::new(MuxWriter::new(wr)));
+
+    let register = Message::request(
+        1,
+        RUNTIME_SERVICE,
+        "RegisterPlugin",
+        &RegisterPluginRequest {
+            plugin_name: PLUGIN_NAME.to_string(),
+            plugin_idx: plugin_idx(),
+        },
+    );
+    writer
+        .lock()
+        .await
+        .send(RUNTIME_SERVICE_CONN, &register)
+        .await?;
+
+    let plugin = Arc::new(Plugin::default());
+    while let Some((conn, msg)) = reader.next().await? {
+        match (conn, msg.msg_type) {
+            (RUNTIME_SERVICE_CONN, MESSAGE_TYPE_RESPONSE) => {
+                ttrpc::response_payload(&msg).context("failed to register the plugin")?;
+                info!("nri: registered as {}-{}", plugin_idx(), PLUGIN_NAME);
+            }
+            (PLUGIN_SERVICE_CONN, MESSAGE_TYPE_REQUEST) => {
+                let plugin = plugin.clone();
+                let writer = writer.clone();
+                tokio::spawn(async move {
+                    let response =
+                        Message::response(msg.stream_id, plugin.handle(&msg.payload).await);
+                    if let Err(e) = writer
+                        .lock()
+                        .await
+                        .send(PLUGIN_SERVICE_CONN, &response)
+                        .await
+                    {
+                        warn!("nri: failed to send response: {}", e);
+                    }
+                });
+            }
+            _ => debug!(
+                "nri: ignoring message of type {} on connection {}",
+                msg.msg_type, conn
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+// Runs the plugin, reconnecting when the runtime restarts. Disabled when no
+// NRI socket is found.
+pub async fn run(stop: impl std::future::Future<Output = ()>) -> anyhow::Result<()> {
+    let Some(socket) = socket() else {
+        info!("nri: no NRI socket found, disabled");
+        stop.await;
+        return Ok(());
+    };
+
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            result = serve(Path::new(&socket)) => match result {
+                Ok(()) => info!("nri: connection closed by the runtime"),
+                Err(e) => warn!("nri: {:#}", e),
+            },
+            _ = &mut stop => return Ok(()),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(RECONNECT_INTERVAL) => {}
+            _ = &mut stop => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stub::StubRuntime;
+    use super::*;
+    use crate::nri_api::LinuxContainer;
+    use crate::rthooks::args::{ContainerArg, CreateContainerArg};
+    use crate::rthooks::{register_hook_at_init, RtHookError, RuntimeHook};
+    use std::sync::Mutex as StdMutex;
+
+    #[derive(Default)]
+    struct RecordingHook {
+        events: StdMutex<Vec<(String, String)>>,
+    }
+
+    #[tonic::async_trait]
+    impl RuntimeHook for RecordingHook {
+        fn name(&self) -> &str {
+            "nri-test"
+        }
+
+        async fn create_container(&self, arg: &CreateContainerArg) -> Result<(), RtHookError> {
+            let event = ("create".to_string(), arg.container_id());
+            self.events.lock().unwrap().push(event);
+            Ok(())
+        }
+
+        async fn delete_container(&self, arg: &ContainerArg) -> Result<(), RtHookError> {
+            let event = ("delete".to_string(), arg.container_id());
+            self.events.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
+
+    fn state_change(event: Event) -> StateChangeEvent {
+        StateChangeEvent {
+            event: event as i32,
+            pod: Some(PodSandbox {
+                name: "nginx-pod".to_string(),
+                uid: "3b673e1d-289e-4210-8ceb-5a253b48d390".to_string(),
+                namespace: "default".to_string(),
+                ..Default::default()
+            }),
+            container: Some(Container {
+                id: "nri-test-container".to_string(),
+                name: "nginx".to_string(),
+                linux: Some(LinuxContainer {
+                    cgroups_path: "kubepods-besteffort-pod3b673e1d_289e_4210_8ceb_5a253b48d390.slice:cri-containerd:nri-test-container".to_string(),
+                }),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_event_mask() {
+        assert_eq!(event_mask(&[Event::RunPodSandbox]), 1);
+        assert_eq!(
+            event_mask(&[Event::CreateContainer, Event::RemoveContainer]),
+            (1 << 3) | (1 << 10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plugin() {
+        let hook = Arc::new(RecordingHook::default());
+        register_hook_at_init(hook.clone());
+
+        let runtime = StubRuntime::new().unwrap();
+        let plugin = tokio::spawn({
+            let path = runtime.path.clone();
+            async move { serve(&path).await }
+        });
+        let mut conn = runtime.accept().await.unwrap();
+
+        let registration = conn.register().await.unwrap();
+        assert_eq!(registration.plugin_name, PLUGIN_NAME);
+        assert_eq!(registration.plugin_idx, DEFAULT_PLUGIN_IDX);
+
+        let config: ConfigureResponse = conn
+            .call(
+                "Configure",
+                &ConfigureRequest {
+                    runtime_name: "stub".to_string(),
+                    request_timeout: 2000,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(config.events, event_mask(&EVENTS));
+
+        let _: SynchronizeResponse = conn
+            .call("Synchronize", &SynchronizeRequest::default())
+            .await
+            .unwrap();
+        let _: Empty = conn
+            .call("StateChange", &state_change(Event::PostCreateContainer))
+            .await
+            .unwrap();
+        let _: Empty = conn
+            .call("StateChange", &state_change(Event::RemoveContainer))
+            .await
+            .unwrap();
+        // Events the plugin is not subscribed to are ignored
+        let _: Empty = conn
+            .call("StateChange", &state_change(Event::RunPodSandbox))
+            .await
+            .unwrap();
+
+        let events: Vec<_> = hook
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, id)| id == "nri-test-container")
+            .map(|(kind, _)| kind.clone())
+            .collect();
+        assert_eq!(events, ["create", "delete"]);
+
+        let err = conn
+            .call::<Empty>("UpdatePodSandbox", &Empty {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown method"));
+
+        drop(conn);
+        plugin.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use super::ttrpc::{
+    self, Message, MuxReader, MuxWriter, MESSAGE_TYPE_REQUEST, MESSAGE_TYPE_RESPONSE,
+    PLUGIN_SERVICE_CONN, RUNTIME_SERVICE_CONN,
+};
+use super::{PLUGIN_SERVICE, RUNTIME_SERVICE};
+use crate::nri_api::{Empty, RegisterPluginRequest};
+use crate::ttrpc_api::Request;
+use prost::Message as _;
+use std::path::PathBuf;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixListener;
+
+// Runtime side of NRI, listening on a socket of its own like the runtime does
+// on /var/run/nri/nri.sock.
+pub(crate) struct StubRuntime {
+    pub path: PathBuf,
+    listener: UnixListener,
+}
+
+impl StubRuntime {
+    pub fn new() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("nri-stub-{}.sock", rand::random::<u64>()));
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { path, listener })
+    }
+
+    pub async fn accept(&self) -> std::io::Result<StubConn> {
+        let (stream, _) = self.listener.accept().await?;
+        let (rd, wr) = stream.into_split();
+        Ok(StubConn {
+            reader: MuxReader::new(rd),
+            writer: MuxWriter::new(wr),
+            next_stream_id: 1,
+        })
+    }
+}
+
+impl Drop for StubRuntime {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub(crate) struct StubConn {
+    reader: MuxReader<OwnedReadHalf>,
+    writer: MuxWriter<OwnedWriteHalf>,
+    next_stream_id: u32,
+}
+
+impl StubConn {
+    // Waits for the registration of the plugin and accepts it
+    pub async fn register(&mut self) -> anyhow::Result<RegisterPluginRequest> {
+        let Some((conn, msg)) = self.reader.next().await? else {
+            anyhow::bail!("plugin closed the connection before registering");
+        };
+        if conn != RUNTIME_SERVICE_CONN || msg.msg_type != MESSAGE_TYPE_REQUEST {
+            anyhow::bail!("unexpected message on connection {}: {:?}", conn, msg);
+        }
+
+        let req = Request::decode(msg.payload.as_slice())?;
+        if req.service != RUNTIME_SERVICE || req.method != "RegisterPlugin" {
+            anyhow::bail!("unexpected request {}/{}", req.service, req.method);
+        }
+        let response = Message::response(msg.stream_id, Ok(Empty {}.encode_to_vec()));
+        self.writer.send(RUNTIME_SERVICE_CONN, &response).await?;
+        Ok(RegisterPluginRequest::decode(req.payload.as_slice())?)
+    }
+
+    // Calls a method of the plugin and returns its response
+    pub async fn call<Resp: prost::Message + Default>(
+        &mut self,
+        method: &str,
+        req: &impl prost::Message,
+    ) -> anyhow::Result<Resp> {
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 2;
+        let msg = Message::request(stream_id, PLUGIN_SERVICE, method, req);
+        self.writer.send(PLUGIN_SERVICE_CONN, &msg).await?;
+
+        let Some((conn, msg)) = self.reader.next().await? else {
+            anyhow::bail!("plugin closed the connection");
+        };
+        if conn != PLUGIN_SERVICE_CONN
+            || msg.msg_type != MESSAGE_TYPE_RESPONSE
+            || msg.stream_id != stream_id
+        {
+            anyhow::bail!("unexpected message on connection {}: {:?}", conn, msg);
+        }
+        let payload = ttrpc::response_payload(&msg)?;
+        Ok(Resp::decode(payload.as_slice())?)
+    }
+}
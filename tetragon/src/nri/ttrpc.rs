@@ -0,0 +1,227 @@
+use crate::ttrpc_api::{Request, Response, Status};
+use bytes::{Buf, BytesMut};
+use prost::Message as _;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// NRI multiplexes two ttrpc connections over its socket: the plugin serves the
+// plugin service on one and calls the runtime service on the other.
+pub const PLUGIN_SERVICE_CONN: u32 = 1;
+pub const RUNTIME_SERVICE_CONN: u32 = 2;
+
+const MUX_HEADER_LEN: usize = 8;
+const MESSAGE_HEADER_LEN: usize = 10;
+const MESSAGE_LENGTH_MAX: usize = 4 << 20;
+
+pub const MESSAGE_TYPE_REQUEST: u8 = 1;
+pub const MESSAGE_TYPE_RESPONSE: u8 = 2;
+
+// gRPC status codes sent back in the responses
+pub const CODE_INVALID_ARGUMENT: i32 = 3;
+pub const CODE_UNIMPLEMENTED: i32 = 12;
+
+// A ttrpc message, without the flags only used by streams
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub stream_id: u32,
+    pub msg_type: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    pub fn request(stream_id: u32, service: &str, method: &str, req: &impl prost::Message) -> Self {
+        let request = Request {
+            service: service.to_string(),
+            method: method.to_string(),
+            payload: req.encode_to_vec(),
+            ..Default::default()
+        };
+        Self {
+            stream_id,
+            msg_type: MESSAGE_TYPE_REQUEST,
+            payload: request.encode_to_vec(),
+        }
+    }
+
+    pub fn response(stream_id: u32, result: Result<Vec<u8>, Status>) -> Self {
+        let response = match result {
+            Ok(payload) => Response {
+                status: None,
+                payload,
+            },
+            Err(status) => Response {
+                status: Some(status),
+                payload: Vec::new(),
+            },
+        };
+        Self {
+            stream_id,
+            msg_type: MESSAGE_TYPE_RESPONSE,
+            payload: response.encode_to_vec(),
+        }
+    }
+}
+
+pub fn status(code: i32, message: impl ToString) -> Status {
+    Status {
+        code,
+        message: message.to_string(),
+    }
+}
+
+// Returns the payload of a response, or its status when it failed
+pub fn response_payload(msg: &Message) -> anyhow::Result<Vec<u8>> {
+    let response = Response::decode(msg.payload.as_slice())?;
+    match response.status {
+        Some(status) if status.code != 0 => Err(anyhow::anyhow!(
+            "request failed with code {}: {}",
+            status.code,
+            status.message
+        )),
+        _ => Ok(response.payload),
+    }
+}
+
+// Reads the ttrpc messages of the multiplexed connections. A message may be
+// split over several frames of its connection.
+pub struct MuxReader<R> {
+    inner: R,
+    buffers: HashMap<u32, BytesMut>,
+}
+
+impl<R: AsyncRead + Unpin> MuxReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffers: HashMap::new(),
+        }
+    }
+
+    // Returns the next message and its connection, or None once the socket is closed
+    pub async fn next(&mut self) -> std::io::Result<Option<(u32, Message)>> {
+        loop {
+            for (conn, buf) in self.buffers.iter_mut() {
+                if let Some(msg) = decode_message(buf)? {
+                    return Ok(Some((*conn, msg)));
+                }
+            }
+
+            let mut header = [0u8; MUX_HEADER_LEN];
+            match self.inner.read_exact(&mut header).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            let conn = u32::from_be_bytes(header[0..4].try_into().unwrap());
+            let len = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+            if len > MESSAGE_HEADER_LEN + MESSAGE_LENGTH_MAX {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("frame of {} bytes is too large", len),
+                ));
+            }
+
+            let mut payload = vec![0u8; len];
+            self.inner.read_exact(&mut payload).await?;
+            self.buffers
+                .entry(conn)
+                .or_default()
+                .extend_from_slice(&payload);
+        }
+    }
+}
+
+fn decode_message(buf: &mut BytesMut) -> std::io::Result<Option<Message>> {
+    if buf.len() < MESSAGE_HEADER_LEN {
+        return Ok(None);
+    }
+    let len = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize;
+    if len > MESSAGE_LENGTH_MAX {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("message of {} bytes is too large", len),
+        ));
+    }
+    if buf.len() < MESSAGE_HEADER_LEN + len {
+        return Ok(None);
+    }
+
+    let stream_id = u32::from_be_bytes(buf[4..8].try_into().unwrap());
+    let msg_type = buf[8];
+    buf.advance(MESSAGE_HEADER_LEN);
+    let payload = buf.split_to(len).to_vec();
+    Ok(Some(Message {
+        stream_id,
+        msg_type,
+        payload,
+    }))
+}
+
+fn encode_frame(conn: u32, msg: &Message) -> Vec<u8> {
+    let len = MESSAGE_HEADER_LEN + msg.payload.len();
+    let mut buf = Vec::with_capacity(MUX_HEADER_LEN + len);
+    buf.extend_from_slice(&conn.to_be_bytes());
+    buf.extend_from_slice(&(len as u32).to_be_bytes());
+    buf.extend_from_slice(&(msg.payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&msg.stream_id.to_be_bytes());
+    buf.push(msg.msg_type);
+    buf.push(0);
+    buf.extend_from_slice(&msg.payload);
+    buf
+}
+
+pub struct MuxWriter<W> {
+    inner: W,
+}
+
+impl<W: AsyncWrite + Unpin> MuxWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    pub async fn send(&mut self, conn: u32, msg: &Message) -> std::io::Result<()> {
+        self.inner.write_all(&encode_frame(conn, msg)).await?;
+        self.inner.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nri_api::Empty;
+
+    #[tokio::test]
+    async fn test_mux_split_frames() {
+        let first = Message::request(1, "service", "First", &Empty {});
+        let second = Message::response(3, Err(status(CODE_UNIMPLEMENTED, "nope")));
+
+        // The first message is split over two frames, interleaved with the
+        // frame of the other connection.
+        let frame = encode_frame(PLUGIN_SERVICE_CONN, &first);
+        let (head, tail) = frame[MUX_HEADER_LEN..].split_at(4);
+        let mut data = Vec::new();
+        for (i, part) in [head, tail].into_iter().enumerate() {
+            data.extend_from_slice(&PLUGIN_SERVICE_CONN.to_be_bytes());
+            data.extend_from_slice(&(part.len() as u32).to_be_bytes());
+            data.extend_from_slice(part);
+            if i == 0 {
+                data.extend_from_slice(&encode_frame(RUNTIME_SERVICE_CONN, &second));
+            }
+        }
+
+        let mut reader = MuxReader::new(data.as_slice());
+        assert_eq!(
+            reader.next().await.unwrap(),
+            Some((RUNTIME_SERVICE_CONN, second.clone()))
+        );
+        assert_eq!(
+            reader.next().await.unwrap(),
+            Some((PLUGIN_SERVICE_CONN, first))
+        );
+        assert_eq!(reader.next().await.unwrap(), None);
+
+        let err = response_payload(&second).unwrap_err();
+        assert!(err.to_string().contains("nope"));
+    }
+}
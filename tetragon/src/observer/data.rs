@@ -0,0 +1,144 @@
+use lru::LruCache;
+use std::mem;
+use std::num::NonZeroUsize;
+use std::sync::{LazyLock, Mutex};
+use tetragon_common::common::MsgCommon;
+use tetragon_common::data::{DataEventDesc, DataEventId, MSG_DATA_HEADER_LEN};
+
+// Keyed by (DataEventId.pid, DataEventId.time)
+type DataKey = (u64, u64);
+
+pub static DATA_CACHE: LazyLock<Mutex<LruCache<DataKey, Vec<u8>>>> =
+    LazyLock::new(|| Mutex::new(LruCache::new(NonZeroUsize::new(1024).unwrap())));
+
+fn data_key(id: &DataEventId) -> DataKey {
+    (id.pid, id.time)
+}
+
+// Appends the chunk of a MsgOpData event to the data with the same DataEventId.
+// Chunks are sent on the same cpu right before the event that refers to them,
+// so they arrive in order.
+pub fn data_add(bytes: &[u8]) -> anyhow::Result<()> {
+    if bytes.len() < MSG_DATA_HEADER_LEN {
+        return Err(anyhow::anyhow!(
+            "MsgData is too small: {} bytes",
+            bytes.len()
+        ));
+    }
+
+    let (common, id) = unsafe {
+        let common = (bytes.as_ptr() as *const MsgCommon).read_unaligned();
+        let id =
+            (bytes[mem::size_of::<MsgCommon>()..].as_ptr() as *const DataEventId).read_unaligned();
+        (common, id)
+    };
+
+    let size = common.size as usize;
+    if size < MSG_DATA_HEADER_LEN || size > bytes.len() {
+        return Err(anyhow::anyhow!(
+            "MsgData has invalid size: {}, received: {} bytes",
+            size,
+            bytes.len()
+        ));
+    }
+    let data = &bytes[MSG_DATA_HEADER_LEN..size];
+
+    let mut cache = DATA_CACHE.lock().unwrap();
+    match cache.get_mut(&data_key(&id)) {
+        Some(prev) => prev.extend_from_slice(data),
+        None => {
+            cache.put(data_key(&id), data.to_vec());
+        }
+    }
+
+    Ok(())
+}
+
+// Returns the reassembled data described by desc and removes it from the cache.
+pub fn data_get(desc: &DataEventDesc) -> anyhow::Result<Vec<u8>> {
+    let id = desc.id;
+    let error = desc.error;
+    if error != 0 {
+        return Err(anyhow::anyhow!(
+            "data event {:?} failed in bpf: {}",
+            data_key(&id),
+            error
+        ));
+    }
+
+    let Some(data) = DATA_CACHE.lock().unwrap().pop(&data_key(&id)) else {
+        return Err(anyhow::anyhow!(
+            "failed to find data for id: {:?}",
+            data_key(&id)
+        ));
+    };
+
+    // make sure we did not lose anything on the way through the perf buffer
+    let size = desc.size as usize;
+    if data.len() != size {
+        return Err(anyhow::anyhow!(
+            "failed to get correct data for id: {:?}, expected: {}, received: {}",
+            data_key(&id),
+            size,
+            data.len()
+        ));
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetragon_common::msg_types::MsgOps;
+
+    fn msg_data(id: DataEventId, data: &[u8]) -> Vec<u8> {
+        let common = MsgCommon {
+            op: MsgOps::MsgOpData as u8,
+            size: (MSG_DATA_HEADER_LEN + data.len()) as u32,
+            ..Default::default()
+        };
+
+        let mut bytes = vec![0u8; MSG_DATA_HEADER_LEN];
+        unsafe {
+            (bytes.as_mut_ptr() as *mut MsgCommon).write_unaligned(common);
+            (bytes[mem::size_of::<MsgCommon>()..].as_mut_ptr() as *mut DataEventId)
+                .write_unaligned(id);
+        }
+        bytes.extend_from_slice(data);
+        // perf buffer samples are padded
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    #[test]
+    fn test_data_reassemble() {
+        let id = DataEventId { pid: 1, time: 100 };
+        data_add(&msg_data(id, b"java\0-jar")).unwrap();
+        data_add(&msg_data(id, b"\0app.jar\0")).unwrap();
+
+        let desc = DataEventDesc {
+            size: 18,
+            id,
+            ..Default::default()
+        };
+        let data = data_get(&desc).unwrap();
+        assert_eq!(data, b"java\0-jar\0app.jar\0");
+
+        // The data is removed once it's consumed
+        assert!(data_get(&desc).is_err());
+    }
+
+    #[test]
+    fn test_data_size_mismatch() {
+        let id = DataEventId { pid: 2, time: 200 };
+        data_add(&msg_data(id, b"abc")).unwrap();
+
+        let desc = DataEventDesc {
+            size: 6,
+            id,
+            ..Default::default()
+        };
+        assert!(data_get(&desc).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+use tokio::sync::watch;
+
+// Like the eventcache of Tetragon, holds the events of a process until an
+// earlier one is sent: the exit and the clones of a process whose exec waits
+// for its pod or its lookups are sent after the exec, with them.
+static HELD: LazyLock<Mutex<HashMap<String, watch::Receiver<()>>>> =
+    LazyLock::new(Default::default);
+
+// Releases the events of the process when dropped
+pub struct Hold {
+    exec_id: String,
+    _tx: watch::Sender<()>,
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        HELD.lock().unwrap().remove(&self.exec_id);
+    }
+}
+
+// Holds the later events of exec_id until the returned guard is dropped
+pub fn hold(exec_id: &str) -> Hold {
+    let (tx, rx) = watch::channel(());
+    HELD.lock().unwrap().insert(exec_id.to_string(), rx);
+    Hold {
+        exec_id: exec_id.to_string(),
+        _tx: tx,
+    }
+}
+
+// Returns a future resolved once the events of exec_id are released, or None
+// when they are not held
+pub fn held(exec_id: &str) -> Option<impl Future<Output = ()> + Send + 'static> {
+    let mut rx = HELD.lock().unwrap().get(exec_id)?.clone();
+    // Only fails once the sender of the guard is dropped
+    Some(async move {
+        let _ = rx.changed().await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_hold() {
+        assert!(held("eventcache-1").is_none());
+
+        let hold = hold("eventcache-1");
+        let released = tokio::spawn(held("eventcache-1").unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!released.is_finished());
+
+        drop(hold);
+        tokio::time::timeout(Duration::from_secs(1), released)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(held("eventcache-1").is_none());
+    }
+}
@@ -1,20 +1,59 @@
+pub mod data;
+pub mod eventcache;
+
 use crate::api::{get_events_response::Event, ProcessExec, ProcessExit};
+use crate::cgrouprate;
+use crate::ktime::to_proto_opt;
 use crate::process;
 use crate::process::cache::cache_get;
+use crate::reader::exec::decode_exit_code;
 use crate::watcher::PodStore;
 use aya::{
     maps::{perf::AsyncPerfEventArray, MapData},
     util::online_cpus,
 };
 use bytes::BytesMut;
-use prost_types::Timestamp;
 use std::convert::TryInto;
 use tetragon_common::common::MsgCommon;
 use tetragon_common::msg_types::MsgOps;
 use tetragon_common::process::{EventBytes, MsgCloneEvent, MsgExecveEvent, MsgExit};
+use tetragon_common::rate::MsgThrottle;
 
 use tracing::*;
 
+// Pages of the perf buffer of each CPU. The default 2 pages can't even hold
+// one MsgData chunk of MSG_DATA_ARG_LEN, this holds the DATA_EVENT_LOOPS chunks
+// of several exec events. Must be a power of two.
+const PERF_BUFFER_PAGES: usize = 128;
+
+fn exec_event(internal: process::ProcessInternal) -> Event {
+    Event::ProcessExec(ProcessExec {
+        process: Some(internal.process),
+        parent: None,
+        ancestors: Vec::new(),
+        binary_hash: internal.binary_hash,
+    })
+}
+
+async fn send_exit(tx: &tokio::sync::broadcast::Sender<Event>, event: &MsgExit, exec_id: String) {
+    let Some(internal) = cache_get(&exec_id).await else {
+        warn!(
+            "MsgExit Not Found process in the cache: pid: {}",
+            event.current.pid
+        );
+        return;
+    };
+
+    let (status, signal) = decode_exit_code(event.info.code);
+    let _ = tx.send(Event::ProcessExit(ProcessExit {
+        process: Some(internal.process),
+        parent: None,
+        signal,
+        status,
+        time: Some(to_proto_opt(event.common.ktime)),
+    }));
+}
+
 pub async fn run_events(
     mut process_events_map: AsyncPerfEventArray<MapData>,
     tx: tokio::sync::broadcast::Sender<Event>,
@@ -27,7 +66,7 @@ pub async fn run_events(
     let num_cpus = cpus.len();
 
     for cpu in cpus {
-        let mut buf = process_events_map.open(cpu, None)?;
+        let mut buf = process_events_map.open(cpu, Some(PERF_BUFFER_PAGES))?;
         let tx = tx.clone();
 
         let store = store.clone();
@@ -65,13 +104,25 @@ pub async fn run_events(
                             info!("MsgOpExecve: {event:?}");
 
                             match process::add_exec_event(&mut event, store.clone()).await {
-                                Ok(internal) => {
-                                    let event = Event::ProcessExec(ProcessExec {
-                                        process: Some(internal.process),
-                                        parent: None,
-                                        ancestors: Vec::new(),
+                                // Held until the pod is known and the lookups are
+                                // done, without blocking the other events of the CPU
+                                Ok((internal, lookups))
+                                    if process::exec_pending(&internal, &lookups, &store) =>
+                                {
+                                    let hold = eventcache::hold(&internal.process.exec_id);
+                                    let nspid = event.process.nspid;
+                                    let store = store.clone();
+                                    let tx = tx.clone();
+                                    tokio::spawn(async move {
+                                        let internal =
+                                            process::complete_exec(internal, lookups, nspid, store)
+                                                .await;
+                                        let _ = tx.send(exec_event(internal));
+                                        drop(hold);
                                     });
-                                    let _ = tx.send(event);
+                                }
+                                Ok((internal, _)) => {
+                                    let _ = tx.send(exec_event(internal));
                                 }
                                 Err(e) => {
                                     warn!("Failed add_exec_event: {}", e);
@@ -87,23 +138,18 @@ pub async fn run_events(
                                 }
                             };
                             info!("MsgExit: {event:?}");
-                            let pid = event.current.pid;
                             let exec_id = process::get_exec_id_from_key(&event.current);
-                            if let Some(internal) = cache_get(&exec_id).await {
-                                let event = Event::ProcessExit(ProcessExit {
-                                    process: Some(internal.process),
-                                    parent: None,
-                                    signal: "".to_string(),
-                                    status: event.info.code,
-                                    time: Some(Timestamp {
-                                        seconds: event.current.ktime as i64,
-                                        nanos: 0,
-                                    }),
-                                });
-                                let _ = tx.send(event);
-                            } else {
-                                warn!("MsgExit Not Found process in the cache: pid: {}", pid)
-                            };
+                            match eventcache::held(&exec_id) {
+                                // Sent after the exec of the process, with its pod
+                                Some(released) => {
+                                    let tx = tx.clone();
+                                    tokio::spawn(async move {
+                                        released.await;
+                                        send_exit(&tx, &event, exec_id).await;
+                                    });
+                                }
+                                None => send_exit(&tx, &event, exec_id).await,
+                            }
                         }
                         MsgOps::MsgOpGenericKprobe => {
                             unimplemented!()
@@ -123,20 +169,59 @@ pub async fn run_events(
                                 }
                             };
                             info!("MsgOpClone: {event:?}");
-                            if let Err(e) = process::add_clone_event(&event, store.clone()).await {
-                                info!("Failed add_clone_event: {}", e);
-                                continue;
+                            let parent_exec_id =
+                                process::get_process_id(event.parent.pid, event.parent.ktime);
+                            match eventcache::held(&parent_exec_id) {
+                                // Copies the parent once its exec is complete, and
+                                // holds the events of the child until then
+                                Some(released) => {
+                                    let hold = eventcache::hold(&process::get_process_id(
+                                        event.tgid,
+                                        event.ktime,
+                                    ));
+                                    let store = store.clone();
+                                    tokio::spawn(async move {
+                                        released.await;
+                                        if let Err(e) =
+                                            process::add_clone_event(&event, store).await
+                                        {
+                                            info!("Failed add_clone_event: {}", e);
+                                        }
+                                        drop(hold);
+                                    });
+                                }
+                                None => {
+                                    if let Err(e) =
+                                        process::add_clone_event(&event, store.clone()).await
+                                    {
+                                        info!("Failed add_clone_event: {}", e);
+                                    }
+                                }
                             }
                         }
                         MsgOps::MsgOpData => {
-                            unimplemented!()
+                            if let Err(e) = data::data_add(&buf[..]) {
+                                warn!("Failed data_add: {}", e);
+                            }
                         }
                         MsgOps::MsgOpCgroup => {
-                            unimplemented!()
+                            // Cgroups are tracked in BPF by TG_CGTRACKER_MAP
+                            trace!("MsgOpCgroup: ignored");
                         }
                         MsgOps::MsgOpLoader => {
                             unimplemented!()
                         }
+                        MsgOps::MsgOpThrottle => {
+                            let event: MsgThrottle = match event.bytes.try_into() {
+                                Ok(e) => e,
+                                Err(e) => {
+                                    warn!("Error converting event to MsgThrottle: {}", e);
+                                    continue;
+                                }
+                            };
+                            info!("MsgOpThrottle: {event:?}");
+                            let _ = tx.send(cgrouprate::throttle_start(&event));
+                        }
                     };
                 }
             }
@@ -71,7 +71,7 @@ pub fn parse_uuid(pod: &Pod) -> Option<uuid::Uuid> {
         .and_then(|uid| uuid::Uuid::parse_str(uid).ok())
 }
 
-fn strip_runtime_prefix(container_id: &str) -> String {
+pub(crate) fn strip_runtime_prefix(container_id: &str) -> String {
     if let Some(idx) = container_id.find("://") {
         return container_id[idx + 3..].to_string();
     }
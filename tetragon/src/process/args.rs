@@ -1,10 +1,105 @@
-pub fn args_decoder(s: &[u8], _flags: u32) -> (String, String) {
-    let args = String::from_utf8(
-        s.split(|&b| b == 0) // Split by null (`\0`) bytes
-            .filter(|s| !s.is_empty()) // Remove empty slices
-            .collect::<Vec<_>>() // Convert to `Vec<Vec<u8>>`
-            .join(&b' '), // Join elements with a space (`b' '`)
-    )
-    .unwrap_or("Unknown".to_owned());
-    (args, "foo".to_string())
+use std::sync::LazyLock;
+use tetragon_common::flags::msg_flags;
+
+// Export the raw argv list in Process.argv, enabled by EXPORT_ARGV=1
+pub static EXPORT_ARGV: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("EXPORT_ARGV")
+        .map(|s| s == "1")
+        .unwrap_or(false)
+});
+
+// s holds argv without argv[0], each argument terminated by NUL. Empty
+// arguments are kept so that argv positions are preserved.
+pub fn args_decoder(s: &[u8], cwd: &[u8], flags: u32) -> (Vec<String>, String) {
+    let s = s.strip_suffix(&[0]).unwrap_or(s);
+    let argv = if s.is_empty() {
+        Vec::new()
+    } else {
+        s.split(|&b| b == 0)
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect()
+    };
+    (argv, cwd_decoder(cwd, flags))
+}
+
+// Joins argv with spaces for Process.arguments. Arguments which contain
+// whitespace or are empty are quoted, so `sh -c "rm -rf /tmp/x"` keeps its
+// boundaries.
+pub fn args_to_string(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// The cwd is resolved in BPF by walking task->fs->pwd. The root directory is
+// not written into the buffer and is reported by EVENT_ROOT_CWD instead.
+fn cwd_decoder(cwd: &[u8], flags: u32) -> String {
+    let flags = flags as u64;
+    if flags & (msg_flags::EVENT_NO_CWD_SUPPORT | msg_flags::EVENT_ERROR_CWD) != 0 {
+        return "".to_string();
+    }
+    if flags & msg_flags::EVENT_ROOT_CWD != 0 {
+        return "/".to_string();
+    }
+
+    let len = cwd.iter().position(|&b| b == 0).unwrap_or(cwd.len());
+    String::from_utf8_lossy(&cwd[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args_decoder_cwd() {
+        let args = b"-la\0/tmp\0";
+        let cwd = b"/home/user\0\0\0";
+
+        let (argv, cwd_str) = args_decoder(args, cwd, msg_flags::EVENT_EXECVE as u32);
+        assert_eq!(argv, vec!["-la", "/tmp"]);
+        assert_eq!(args_to_string(&argv), "-la /tmp");
+        assert_eq!(cwd_str, "/home/user");
+    }
+
+    #[test]
+    fn test_args_decoder_boundaries() {
+        let args = b"-c\0rm -rf /tmp/x\0\0last\0";
+
+        let (argv, _) = args_decoder(args, b"", msg_flags::EVENT_EXECVE as u32);
+        assert_eq!(argv, vec!["-c", "rm -rf /tmp/x", "", "last"]);
+        assert_eq!(args_to_string(&argv), r#"-c "rm -rf /tmp/x" "" last"#);
+    }
+
+    #[test]
+    fn test_args_to_string_escape() {
+        let argv = vec![r#"say "hi" \o/"#.to_string()];
+        assert_eq!(args_to_string(&argv), r#""say \"hi\" \\o/""#);
+    }
+
+    #[test]
+    fn test_args_decoder_no_args() {
+        let (argv, _) = args_decoder(b"", b"", msg_flags::EVENT_EXECVE as u32);
+        assert!(argv.is_empty());
+    }
+
+    #[test]
+    fn test_args_decoder_root_cwd() {
+        let flags = (msg_flags::EVENT_EXECVE | msg_flags::EVENT_ROOT_CWD) as u32;
+        let (_, cwd_str) = args_decoder(b"", b"\0\0", flags);
+        assert_eq!(cwd_str, "/");
+    }
+
+    #[test]
+    fn test_args_decoder_error_cwd() {
+        let flags = (msg_flags::EVENT_EXECVE | msg_flags::EVENT_ERROR_CWD) as u32;
+        let (_, cwd_str) = args_decoder(b"", b"/garbage\0", flags);
+        assert_eq!(cwd_str, "");
+    }
 }
@@ -20,3 +20,13 @@ pub async fn cache_get(exec_id: &String) -> Option<ProcessInternal> {
     let mut cache = CACHE.lock().await;
     cache.get(exec_id).cloned()
 }
+
+// Sets the fields of a process still in the cache that were only known once
+// its exec was completed, like its pod or its user
+pub async fn cache_update(process: &ProcessInternal) {
+    let mut cache = CACHE.lock().await;
+    if let Some(cached) = cache.get_mut(&process.process.exec_id) {
+        cached.process = process.process.clone();
+        cached.binary_hash = process.binary_hash.clone();
+    }
+}
@@ -0,0 +1,56 @@
+use crate::api::UserRecord;
+use crate::process::lookup::Lookup;
+use crate::process::ProcessInternal;
+use crate::reader::user::{get_passwd, Passwd};
+use std::sync::{Arc, LazyLock};
+
+// Export Process.cap and Process.process_credentials, enabled by ENABLE_PROCESS_CRED=1
+pub static ENABLE_PROCESS_CRED: LazyLock<bool> =
+    LazyLock::new(|| env_enabled("ENABLE_PROCESS_CRED"));
+
+// Export Process.ns, enabled by ENABLE_PROCESS_NS=1
+pub static ENABLE_PROCESS_NS: LazyLock<bool> = LazyLock::new(|| env_enabled("ENABLE_PROCESS_NS"));
+
+// Resolve Process.user from /etc/passwd, enabled by USERNAME_METADATA=unix
+pub static USERNAME_METADATA: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("USERNAME_METADATA")
+        .map(|s| s == "unix")
+        .unwrap_or(false)
+});
+
+fn env_enabled(name: &str) -> bool {
+    std::env::var(name).map(|s| s == "1").unwrap_or(false)
+}
+
+// Copies the fields computed in ProcessInternal into the API process,
+// depending on the enabled options.
+pub fn enrich_process(internal: &mut ProcessInternal) {
+    if *ENABLE_PROCESS_CRED {
+        internal.process.cap = Some(internal.capabilities.clone());
+        internal.process.process_credentials = Some(internal.api_creds.clone());
+    }
+
+    if *ENABLE_PROCESS_NS {
+        internal.process.ns = Some(internal.namespaces.clone());
+    }
+}
+
+// Returns the lookup of the /etc/passwd of the process, when Process.user is
+// enabled
+pub fn user_passwd(internal: &ProcessInternal) -> Option<Lookup<Arc<Passwd>>> {
+    if !*USERNAME_METADATA {
+        return None;
+    }
+    let pid = internal.process.pid?;
+    let mnt_inum = internal.namespaces.mnt.as_ref()?.inum;
+    Some(get_passwd(pid, mnt_inum))
+}
+
+// Sets Process.user from the /etc/passwd of the process
+pub fn enrich_user(internal: &mut ProcessInternal, passwd: &Passwd) {
+    internal.process.user = internal
+        .process
+        .uid
+        .and_then(|uid| passwd.user_name(uid))
+        .map(|name| UserRecord { name });
+}
@@ -0,0 +1,100 @@
+use crate::api::EnvVar;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+const REDACTED: &str = "*****";
+
+// Comma separated names of the environment variables exported in exec events
+pub static ENVS_ALLOWLIST: LazyLock<HashSet<String>> = LazyLock::new(|| {
+    std::env::var("ENVS_ALLOWLIST")
+        .map(|s| parse_list(&s))
+        .unwrap_or_default()
+});
+
+// Comma separated names of the environment variables whose values are redacted
+pub static ENVS_REDACTLIST: LazyLock<HashSet<String>> = LazyLock::new(|| {
+    std::env::var("ENVS_REDACTLIST")
+        .map(|s| parse_list(&s))
+        .unwrap_or_default()
+});
+
+// Whether the BPF programs send the environment of the processes
+pub fn envs_enabled() -> bool {
+    !ENVS_ALLOWLIST.is_empty()
+}
+
+fn parse_list(s: &str) -> HashSet<String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn envs_decoder(s: &[u8]) -> Vec<EnvVar> {
+    envs_filter(s, &ENVS_ALLOWLIST, &ENVS_REDACTLIST)
+}
+
+// s holds `KEY=VALUE` entries terminated by NUL, read from mm->env_start.
+fn envs_filter(s: &[u8], allowlist: &HashSet<String>, redactlist: &HashSet<String>) -> Vec<EnvVar> {
+    if allowlist.is_empty() {
+        return Vec::new();
+    }
+
+    s.split(|&b| b == 0)
+        .filter_map(|env| {
+            let env = String::from_utf8_lossy(env);
+            let (key, value) = env.split_once('=')?;
+            if !allowlist.contains(key) {
+                return None;
+            }
+
+            let value = if redactlist.contains(key) {
+                REDACTED.to_string()
+            } else {
+                value.to_string()
+            };
+            Some(EnvVar {
+                key: key.to_string(),
+                value,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envs_filter() {
+        let envs =
+            b"PATH=/usr/bin\0LD_PRELOAD=/tmp/evil.so\0HTTP_PROXY=http://u:p@proxy:3128\0EMPTY=\0";
+        let allowlist = parse_list("LD_PRELOAD, HTTP_PROXY,EMPTY");
+        let redactlist = parse_list("HTTP_PROXY");
+
+        let envs = envs_filter(envs, &allowlist, &redactlist);
+        let envs: Vec<_> = envs
+            .iter()
+            .map(|e| (e.key.as_str(), e.value.as_str()))
+            .collect();
+        assert_eq!(
+            envs,
+            vec![
+                ("LD_PRELOAD", "/tmp/evil.so"),
+                ("HTTP_PROXY", REDACTED),
+                ("EMPTY", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_envs_filter_no_allowlist() {
+        let envs = envs_filter(
+            b"LD_PRELOAD=/tmp/evil.so\0",
+            &HashSet::new(),
+            &HashSet::new(),
+        );
+        assert!(envs.is_empty());
+    }
+}
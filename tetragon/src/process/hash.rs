@@ -0,0 +1,130 @@
+use crate::process::lookup::{Lookup, LookupCache};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tetragon_common::process::MsgImaHash;
+use tracing::*;
+
+// Hash the executed binaries, enabled by EXPORT_BINARY_HASH=1
+pub static EXPORT_BINARY_HASH: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("EXPORT_BINARY_HASH")
+        .map(|s| s == "1")
+        .unwrap_or(false)
+});
+
+// Keyed by (dev, inode, mtime sec, mtime nsec) of the binary, so a binary
+// replaced in place is hashed again.
+type HashKey = (u64, u64, i64, i64);
+
+static HASH_CACHE: LazyLock<LookupCache<HashKey, String>> =
+    LazyLock::new(|| LookupCache::new(1024));
+
+// Returns the hash of the binary executed by pid as `<algo>:<hex>`. The IMA
+// hash computed in BPF is used when available, otherwise the binary is hashed
+// with SHA-256 in userspace. The exec of a binary not hashed yet is held on the
+// Pending until it is done.
+pub fn binary_hash(pid: u32, ima: &MsgImaHash) -> Lookup<String> {
+    if !*EXPORT_BINARY_HASH {
+        return Lookup::Cached(String::new());
+    }
+
+    if let Some(hash) = ima_hash_decoder(ima) {
+        return Lookup::Cached(hash);
+    }
+
+    proc_exe_hash(pid).unwrap_or_else(|e| {
+        debug!("binary_hash: failed to hash /proc/{}/exe: {}", pid, e);
+        Lookup::Cached(String::new())
+    })
+}
+
+// Values of enum hash_algo in include/uapi/linux/hash_info.h
+fn ima_hash_algo(algo: i32) -> Option<(&'static str, usize)> {
+    match algo {
+        1 => Some(("md5", 16)),
+        2 => Some(("sha1", 20)),
+        4 => Some(("sha256", 32)),
+        5 => Some(("sha384", 48)),
+        6 => Some(("sha512", 64)),
+        7 => Some(("sha224", 28)),
+        _ => None,
+    }
+}
+
+fn ima_hash_decoder(ima: &MsgImaHash) -> Option<String> {
+    let (name, len) = ima_hash_algo(ima.algo)?;
+    Some(format!("{}:{}", name, to_hex(&ima.value[..len])))
+}
+
+// Returns the cached hash of the binary, or hashes it in a blocking task.
+// Hashing a large binary takes too long for the event readers.
+fn proc_exe_hash(pid: u32) -> io::Result<Lookup<String>> {
+    // The file is opened first, so the metadata and the content are from the
+    // same binary even if /proc/<pid>/exe changes in the meantime.
+    let file = File::open(format!("/proc/{}/exe", pid))?;
+    let meta = file.metadata()?;
+    let key = (meta.dev(), meta.ino(), meta.mtime(), meta.mtime_nsec());
+
+    Ok(HASH_CACHE.get(key, move || async move {
+        let hash = tokio::task::spawn_blocking(move || file_hash(file))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+        match hash {
+            Ok(hash) => (hash, None),
+            Err(e) => {
+                debug!("binary_hash: failed to hash /proc/{}/exe: {}", pid, e);
+                (String::new(), Some(Duration::ZERO))
+            }
+        }
+    }))
+}
+
+fn file_hash(mut file: File) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("sha256:{}", to_hex(&hasher.finalize())))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ima_hash_decoder() {
+        let mut ima = MsgImaHash::default();
+        assert_eq!(ima_hash_decoder(&ima), None);
+
+        ima.algo = 2;
+        ima.value[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            ima_hash_decoder(&ima).unwrap(),
+            format!("sha1:deadbeef{}", "0".repeat(32))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_proc_exe_hash() {
+        let pid = std::process::id();
+        let data = std::fs::read(format!("/proc/{}/exe", pid)).unwrap();
+        let expected = format!("sha256:{}", to_hex(&Sha256::digest(&data)));
+
+        // Hashed in a blocking task, then served from the cache
+        let Lookup::Pending(pending) = proc_exe_hash(pid).unwrap() else {
+            panic!("hashed before the lookup");
+        };
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        assert_eq!(pending.wait(deadline).await, Some(expected.clone()));
+        assert!(matches!(proc_exe_hash(pid).unwrap(), Lookup::Cached(hash) if hash == expected));
+    }
+}
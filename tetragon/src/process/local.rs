@@ -0,0 +1,209 @@
+use crate::api::Container;
+use crate::cgroups::linux::process_cgroup_path;
+use crate::docker;
+use crate::process::lookup::{Lookup, LookupCache};
+use crate::process::ProcessInternal;
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tracing::*;
+
+// How long the resolved containers and units are kept, and the failed lookups
+// before they are tried again, like when the runtime is restarting
+const RESOLVED_TTL: Duration = Duration::from_secs(600);
+const FAILED_TTL: Duration = Duration::from_secs(30);
+
+// Docker or Podman socket, looked up once
+static ENDPOINT: LazyLock<Option<String>> = LazyLock::new(|| {
+    let endpoint = docker::endpoint();
+    match &endpoint {
+        Some(socket) => info!("Resolving local containers from {}", socket),
+        None => info!("No Docker or Podman socket found, containers are not resolved"),
+    }
+    endpoint
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Local {
+    Container(Container),
+    Unit(String),
+    Unknown,
+}
+
+// Containers already inspected by container id, None when the inspection failed
+static CONTAINERS: LazyLock<LookupCache<String, Option<Container>>> =
+    LazyLock::new(|| LookupCache::new(256));
+
+// Container or unit of the cgroups, by cgroup id
+static CGROUPS: LazyLock<LookupCache<u64, Local>> = LazyLock::new(|| LookupCache::new(1024));
+
+// Cgroup names of Docker (docker-<id>.scope with systemd, <id> below /docker
+// otherwise) and Podman (libpod-<id>.scope)
+static CONTAINER_CGROUP_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:docker-|libpod-)?([0-9a-f]{64})(?:\.scope)?$").unwrap());
+
+// Returns the id of the container of a cgroup path, like
+// /system.slice/docker-<id>.scope. The process may be in a cgroup nested in
+// the one of the container.
+fn container_id_from_path(path: &str) -> Option<String> {
+    path.rsplit('/')
+        .find_map(|name| CONTAINER_CGROUP_RE.captures(name))
+        .map(|caps| caps[1].to_string())
+}
+
+// Returns the systemd unit of a cgroup path, like nginx.service for
+// /system.slice/nginx.service or session-2.scope for a login session
+fn systemd_unit_from_path(path: &str) -> Option<String> {
+    path.rsplit('/')
+        .find(|name| name.ends_with(".service") || name.ends_with(".scope"))
+        .map(|name| name.to_string())
+}
+
+async fn inspect(container_id: String) -> (Option<Container>, Option<Duration>) {
+    let Some(socket) = ENDPOINT.as_deref() else {
+        return (None, Some(RESOLVED_TTL));
+    };
+    match docker::inspect(socket, &container_id).await {
+        Ok(container) => (container, Some(RESOLVED_TTL)),
+        Err(e) => {
+            debug!("Failed to inspect container {}: {:#}", container_id, e);
+            (None, Some(FAILED_TTL))
+        }
+    }
+}
+
+// Returns the container from the runtime, or only its id when the runtime
+// doesn't know it, with how long to keep it
+async fn get_container(container_id: String) -> (Container, Duration) {
+    let id = container_id.clone();
+    let container = match CONTAINERS.get(id.clone(), move || inspect(id)) {
+        Lookup::Cached(container) => container,
+        // The inspection is bounded by the request timeout
+        Lookup::Pending(pending) => {
+            let deadline = tokio::time::Instant::now() + 2 * docker::REQUEST_TIMEOUT;
+            pending.wait(deadline).await.flatten()
+        }
+    };
+    match container {
+        Some(container) => (container, RESOLVED_TTL),
+        None => (
+            Container {
+                id: container_id,
+                ..Default::default()
+            },
+            FAILED_TTL,
+        ),
+    }
+}
+
+// Resolves the container or unit of a process from its cgroup path. When the
+// process is already gone, the cgroup name from the kernel is the best we have.
+async fn resolve(path: Option<String>, cgroup_name: String) -> (Local, Option<Duration>) {
+    let (path, ttl) = match path {
+        Some(path) => (path, RESOLVED_TTL),
+        None => (cgroup_name, FAILED_TTL),
+    };
+
+    if let Some(container_id) = container_id_from_path(&path) {
+        let (container, container_ttl) = get_container(container_id).await;
+        return (Local::Container(container), Some(ttl.min(container_ttl)));
+    }
+    match systemd_unit_from_path(&path) {
+        Some(unit) => (Local::Unit(unit), Some(ttl)),
+        None => (Local::Unknown, Some(ttl)),
+    }
+}
+
+// Returns the lookup of the Docker or Podman container of the process, or of
+// its systemd unit outside of containers, by its cgroup. Only used without
+// Kubernetes, which has the pods.
+pub fn get_local(proc: &ProcessInternal) -> Option<Lookup<Local>> {
+    let pid = proc.process.pid?;
+    if proc.cgrpid == 0 {
+        return None;
+    }
+
+    let cgroup_name = proc.process.docker.clone();
+    Some(CGROUPS.get(proc.cgrpid, move || {
+        // Read on a miss of the event reader, while the process most likely
+        // still runs
+        let path = process_cgroup_path(pid).ok();
+        resolve(path, cgroup_name)
+    }))
+}
+
+pub fn enrich_local(proc: &mut ProcessInternal, local: Local) {
+    match local {
+        Local::Container(container) => proc.process.container = Some(container),
+        Local::Unit(unit) => proc.process.systemd_unit = unit,
+        Local::Unknown => {}
+    }
+}
+
+// Like get_local, without cache. Used for the processes found at startup,
+// which are not added by the event readers and have no cgroup id.
+pub async fn resolve_local(proc: &mut ProcessInternal) {
+    let Some(pid) = proc.process.pid else {
+        return;
+    };
+    let path = process_cgroup_path(pid).ok();
+    let (local, _) = resolve(path, proc.process.docker.clone()).await;
+    enrich_local(proc, local);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147";
+
+    #[test]
+    fn test_container_id_from_path() {
+        for path in [
+            format!("/system.slice/docker-{}.scope", ID),
+            format!("/docker/{}", ID),
+            format!("/machine.slice/libpod-{}.scope/container", ID),
+            format!(
+                "/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{}.scope",
+                ID
+            ),
+        ] {
+            assert_eq!(
+                container_id_from_path(&path).as_deref(),
+                Some(ID),
+                "{}",
+                path
+            );
+        }
+
+        assert_eq!(container_id_from_path("/system.slice/nginx.service"), None);
+        assert_eq!(
+            container_id_from_path(&format!("/system.slice/libpod-conmon-{}.scope", ID)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_systemd_unit_from_path() {
+        assert_eq!(
+            systemd_unit_from_path("/system.slice/nginx.service").as_deref(),
+            Some("nginx.service")
+        );
+        assert_eq!(
+            systemd_unit_from_path("/user.slice/user-1000.slice/session-2.scope").as_deref(),
+            Some("session-2.scope")
+        );
+        assert_eq!(
+            systemd_unit_from_path(
+                "/user.slice/user-1000.slice/user@1000.service/app.slice/foot-server.service"
+            )
+            .as_deref(),
+            Some("foot-server.service")
+        );
+        assert_eq!(systemd_unit_from_path("/"), None);
+        assert_eq!(
+            systemd_unit_from_path("/init.scope").as_deref(),
+            Some("init.scope")
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use lru::LruCache;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+// Caches the results of lookups too slow for the event readers, like hashing
+// a binary. A miss starts the lookup in a task of its own and returns a
+// Pending, which the exec is held on: even the first exec of a binary gets the
+// result. Each key is looked up once at a time.
+pub struct LookupCache<K, V> {
+    entries: Mutex<LruCache<K, Entry<V>>>,
+    pending: Mutex<HashMap<K, watch::Receiver<Option<V>>>>,
+}
+
+struct Entry<V> {
+    value: V,
+    // None when kept until evicted
+    expires: Option<Instant>,
+}
+
+pub enum Lookup<V> {
+    Cached(V),
+    Pending(Pending<V>),
+}
+
+// Result of a running lookup
+pub struct Pending<V>(watch::Receiver<Option<V>>);
+
+impl<V: Clone> Pending<V> {
+    // Waits for the result until deadline, None when the lookup takes longer
+    pub async fn wait(mut self, deadline: tokio::time::Instant) -> Option<V> {
+        let result = tokio::time::timeout_at(deadline, self.0.wait_for(Option::is_some)).await;
+        match result {
+            Ok(Ok(value)) => value.clone(),
+            _ => None,
+        }
+    }
+}
+
+impl<K, V> LookupCache<K, V>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new(cap: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(NonZeroUsize::new(cap).unwrap())),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Returns the cached value of key, or the Pending of its lookup, started
+    // unless already running. The lookup returns the value and how long to keep
+    // it, None to keep it until evicted and zero not to keep it, like failures.
+    pub fn get<F>(&'static self, key: K, lookup: impl FnOnce() -> F) -> Lookup<V>
+    where
+        F: Future<Output = (V, Option<Duration>)> + Send + 'static,
+    {
+        if let Some(entry) = self.entries.lock().unwrap().get(&key) {
+            if !entry
+                .expires
+                .is_some_and(|expires| expires <= Instant::now())
+            {
+                return Lookup::Cached(entry.value.clone());
+            }
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(rx) = pending.get(&key) {
+            return Lookup::Pending(Pending(rx.clone()));
+        }
+        let (tx, rx) = watch::channel(None);
+        pending.insert(key.clone(), rx.clone());
+        drop(pending);
+
+        let lookup = lookup();
+        tokio::spawn(async move {
+            let (value, ttl) = lookup.await;
+            if ttl != Some(Duration::ZERO) {
+                let entry = Entry {
+                    value: value.clone(),
+                    expires: ttl.map(|ttl| Instant::now() + ttl),
+                };
+                self.entries.lock().unwrap().put(key.clone(), entry);
+            }
+            self.pending.lock().unwrap().remove(&key);
+            let _ = tx.send(Some(value));
+        });
+        Lookup::Pending(Pending(rx))
+    }
+
+    // Returns the cached value of key, even when expired, so that a lookup can
+    // reuse it when nothing changed
+    pub fn get_stale(&self, key: &K) -> Option<V> {
+        self.entries
+            .lock()
+            .unwrap()
+            .peek(key)
+            .map(|entry| entry.value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static CACHE: LazyLock<LookupCache<u32, String>> = LazyLock::new(|| LookupCache::new(16));
+
+    fn deadline() -> tokio::time::Instant {
+        tokio::time::Instant::now() + Duration::from_secs(1)
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let lookup = |value: &str, ttl| {
+            let value = value.to_string();
+            move || async move { (value, ttl) }
+        };
+
+        // Shared by the misses while running, then cached
+        let Lookup::Pending(first) = CACHE.get(1, lookup("one", None)) else {
+            panic!("cached before the lookup");
+        };
+        let Lookup::Pending(second) = CACHE.get(1, lookup("other", None)) else {
+            panic!("cached before the lookup");
+        };
+        assert_eq!(first.wait(deadline()).await.as_deref(), Some("one"));
+        assert_eq!(second.wait(deadline()).await.as_deref(), Some("one"));
+        assert!(matches!(CACHE.get(1, lookup("other", None)), Lookup::Cached(v) if v == "one"));
+
+        // Not kept with a zero ttl
+        let Lookup::Pending(failed) = CACHE.get(2, lookup("", Some(Duration::ZERO))) else {
+            panic!("cached before the lookup");
+        };
+        assert_eq!(failed.wait(deadline()).await.as_deref(), Some(""));
+        assert!(matches!(
+            CACHE.get(2, lookup("two", None)),
+            Lookup::Pending(_)
+        ));
+
+        // Looked up again once expired
+        let ttl = Some(Duration::from_millis(10));
+        let Lookup::Pending(short) = CACHE.get(3, lookup("three", ttl)) else {
+            panic!("cached before the lookup");
+        };
+        assert_eq!(short.wait(deadline()).await.as_deref(), Some("three"));
+        assert!(matches!(
+            CACHE.get(3, lookup("three", ttl)),
+            Lookup::Cached(_)
+        ));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(matches!(
+            CACHE.get(3, lookup("three", ttl)),
+            Lookup::Pending(_)
+        ));
+    }
+}
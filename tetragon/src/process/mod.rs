@@ -1,5 +1,10 @@
 pub mod args;
 pub mod cache;
+pub mod enrich;
+pub mod envs;
+pub mod hash;
+pub mod local;
+pub mod lookup;
 pub mod podinfo;
 pub mod procfs;
 
@@ -7,24 +12,47 @@ use crate::api::{
     BinaryProperties, Capabilities, FileProperties, InodeProperties, Namespaces,
     Process as ApiProcess, ProcessCredentials,
 };
+use crate::cgidmap;
 use crate::ktime::to_proto_opt;
-use crate::process::args::args_decoder;
-use crate::process::cache::{cache_add, cache_get};
-use crate::process::podinfo::get_pod_info;
+use crate::observer::data::data_get;
+use crate::process::args::{args_decoder, args_to_string, EXPORT_ARGV};
+use crate::process::cache::{cache_add, cache_get, cache_update};
+use crate::process::enrich::{enrich_process, enrich_user, user_passwd};
+use crate::process::envs::envs_decoder;
+use crate::process::hash::binary_hash;
+use crate::process::local::{enrich_local, get_local, Local};
+use crate::process::lookup::{Lookup, Pending};
+use crate::process::podinfo::{get_pod_info, wait_pod_info};
 use crate::reader::caps::{
-    get_msg_capabilities, get_privileges_changed_reasons, get_secure_bits_types, EXECVE_SETGID,
-    EXECVE_SETUID,
+    get_msg_capabilities, get_privileges_changed_reasons, get_secure_bits_types,
 };
-use crate::reader::namespace::get_msg_namespaces;
+use crate::reader::namespace::{get_msg_namespaces, get_msg_user_namespace};
+use crate::reader::path::get_binary_absolute_path;
 use crate::reader::proc::INVALID_UID;
-use crate::watcher::PodStore;
+use crate::reader::user::Passwd;
+use crate::watcher::{PodStore, ENABLE_K8S_API};
 use anyhow;
 use base64::{engine::general_purpose, Engine as _};
 use core::mem;
+use prost_types::Timestamp;
+use std::sync::Arc;
+use std::time::Duration;
+use tetragon_common::data::DataEventDesc;
 use tetragon_common::flags::msg_flags;
-use tetragon_common::process::{MsgCloneEvent, MsgExecveEvent, MsgExecveKey, MsgExit, MsgProcess};
+use tetragon_common::flags::secureexec_flags::{EXECVE_SETGID, EXECVE_SETUID};
+use tetragon_common::process::{
+    MsgCloneEvent, MsgExecveEvent, MsgExecveKey, MsgExit, MsgImaHash, MsgProcess,
+};
 use tracing::*;
 
+// Exec events of a container whose pod is not known yet are held for at most
+// this long, until the informer sees the pod
+const POD_WAIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Exec events are held for at most this long for the lookups that missed their
+// cache, like hashing a binary seen for the first time
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Debug, Default, Clone)]
 pub struct ProcessInternal {
     pub process: ApiProcess,
@@ -32,6 +60,9 @@ pub struct ProcessInternal {
     pub api_creds: ProcessCredentials,
     pub namespaces: Namespaces,
     pub api_binary_prop: BinaryProperties,
+    pub binary_hash: String,
+    // cgroup id of the container of the process, 0 outside of containers
+    pub cgrpid: u64,
     pub refcnt: u32,
 }
 
@@ -109,7 +140,35 @@ pub fn init_process_internal_exec(
         .map(|valid_str| valid_str.trim_end_matches('\0').to_string())
         .map_err(|_| anyhow::anyhow!("Error converting container_id to String"))?;
 
-    let (args, cwd) = args_decoder(&event.exe.args, process.flags);
+    let filename = exe_data(
+        &event.exe.filename,
+        event.exe.filename.len(),
+        msg_flags::EVENT_DATA_FILENAME,
+        msg_flags::EVENT_ERROR_FILENAME,
+        &mut process,
+    );
+    let args_len = (event.exe.len as usize).min(event.exe.args.len());
+    let args = exe_data(
+        &event.exe.args,
+        args_len,
+        msg_flags::EVENT_DATA_ARGS,
+        msg_flags::EVENT_ERROR_ARGS,
+        &mut process,
+    );
+    let (argv, cwd) = args_decoder(&args, &event.exe.cwd, process.flags);
+    let args = args_to_string(&argv);
+
+    let environment_variables = if process.flags as u64 & msg_flags::EVENT_DATA_ENVS != 0 {
+        match data_get(&event.exe.envs) {
+            Ok(envs) => envs_decoder(&envs),
+            Err(e) => {
+                warn!("ExecveEvent: failed to get envs data event: {}", e);
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
 
     let parent_exec_id = if parent.pid != 0 {
         get_exec_id_from_key(parent)
@@ -120,25 +179,30 @@ pub fn init_process_internal_exec(
     let creds = event.creds;
     let exec_id = get_exec_id(&process);
 
-    let proto_pod = get_pod_info(
-        event.kube.cgrpid,
-        "filename",
-        &args,
-        process.nspid,
-        store.clone(),
-    );
-
-    let api_caps = get_msg_capabilities(&event.creds.caps);
+    // Processes in nested cgroups are resolved by the container cgroup. The
+    // cgidmap may be keyed by the cgroup below it though (crun subgroups).
+    let kube = event.kube;
+    let cgrpid = if kube.cgrp_tracker_id != 0 && cgidmap::get(kube.cgrp_tracker_id).is_some() {
+        kube.cgrp_tracker_id
+    } else {
+        kube.cgrpid
+    };
 
-    let len = event
-        .exe
-        .filename
+    let len = filename
         .iter()
         .position(|&x| x == 0)
-        .unwrap_or(event.exe.filename.len());
-    let binary = std::str::from_utf8(&event.exe.filename[..len])
-        .unwrap()
-        .to_string();
+        .unwrap_or(filename.len());
+    let binary = String::from_utf8_lossy(&filename[..len]).into_owned();
+    // filename is relative when the binary was executed like `./foo`
+    let binary = if cwd.is_empty() {
+        binary
+    } else {
+        get_binary_absolute_path(&binary, &cwd)
+    };
+
+    let proto_pod = get_pod_info(cgrpid, &binary, &args, process.nspid, store.clone());
+
+    let api_caps = get_msg_capabilities(&event.creds.caps);
 
     let api_ns = get_msg_namespaces(event.ns)?;
 
@@ -152,8 +216,8 @@ pub fn init_process_internal_exec(
         fsuid: Some(creds.fsuid),
         fsgid: Some(creds.fsgid),
         securebits: get_secure_bits_types(creds.securebits),
-        caps: None,
-        user_ns: None,
+        caps: Some(api_caps.clone()),
+        user_ns: get_msg_user_namespace(&creds.user_ns),
     };
 
     let mut api_binary_prop = BinaryProperties {
@@ -166,7 +230,7 @@ pub fn init_process_internal_exec(
         api_binary_prop.setuid = Some(creds.euid);
     }
     if process.secureexec & EXECVE_SETGID != 0 {
-        api_binary_prop.setuid = Some(creds.egid);
+        api_binary_prop.setgid = Some(creds.egid);
     }
     api_binary_prop.privileges_changed = get_privileges_changed_reasons(process.secureexec);
 
@@ -181,6 +245,13 @@ pub fn init_process_internal_exec(
         })
     }
 
+    // Only exported when the binary changed privileges or is not on the filesystem
+    let binary_properties = if process.secureexec != 0 || api_binary_prop.file.is_some() {
+        Some(api_binary_prop.clone())
+    } else {
+        None
+    };
+
     if process.pid != process.tid {
         warn!("ExecveEvent: process PID and TID mismatch");
         // Explicitly reset TID to be PID
@@ -189,7 +260,7 @@ pub fn init_process_internal_exec(
 
     let flags = process.flags;
 
-    Ok(ProcessInternal {
+    let mut proc = ProcessInternal {
         process: ApiProcess {
             pid: Some(process.pid),
             tid: Some(process.tid),
@@ -207,24 +278,163 @@ pub fn init_process_internal_exec(
             refcnt: 0,
             cap: None,
             ns: None,
-            binary_properties: None,
+            binary_properties,
             process_credentials: None,
             user: None,
             in_init_tree: None,
+            argv: if *EXPORT_ARGV { argv } else { Vec::new() },
+            environment_variables,
         },
         capabilities: api_caps,
         api_creds,
         api_binary_prop,
         namespaces: api_ns,
+        binary_hash: String::new(),
+        cgrpid,
         refcnt: 1,
-    })
+    };
+    enrich_process(&mut proc);
+
+    Ok(proc)
+}
+
+// Returns the first len bytes of an exe field, or the data sent as MsgOpData
+// events when data_flag is set. In that case the field holds a DataEventDesc.
+fn exe_data(
+    bytes: &[u8],
+    len: usize,
+    data_flag: u64,
+    error_flag: u64,
+    process: &mut MsgProcess,
+) -> Vec<u8> {
+    if process.flags as u64 & data_flag == 0 {
+        return bytes[..len].to_vec();
+    }
+
+    let Some(desc) = DataEventDesc::from_bytes(bytes) else {
+        process.flags |= error_flag as u32;
+        return Vec::new();
+    };
+    match data_get(&desc) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("ExecveEvent: failed to get data event: {}", e);
+            process.flags |= error_flag as u32;
+            Vec::new()
+        }
+    }
+}
+
+// Binaries of the OCI runtimes and shims that start the exec sessions, like
+// runc exec started by containerd-shim-runc-v2 or conmon
+const RUNTIME_EXEC_PARENTS: [&str; 5] = ["runc", "crun", "youki", "runsc", "conmon"];
+
+// The container init is exec'd before the container is reported started, and
+// the start time of the pod status is only precise to the second
+const CONTAINER_INIT_GRACE_SECS: i64 = 2;
+
+fn is_runtime_exec_parent(binary: &str) -> bool {
+    let name = binary.rsplit('/').next().unwrap_or(binary);
+    RUNTIME_EXEC_PARENTS.contains(&name) || name.starts_with("containerd-shim")
+}
+
+fn started_after_container(exec: &Timestamp, container: &Timestamp) -> bool {
+    exec.seconds >= container.seconds + CONTAINER_INIT_GRACE_SECS
+}
+
+// Processes started by the container runtime in a running container, like a
+// kubectl exec session, have the runtime as parent. So does the container
+// init, which is told apart by running before the container start time: it is
+// not always the first process of its pid namespace, like with
+// shareProcessNamespace or hostPID.
+async fn mark_exec_session(proc: &mut ProcessInternal, nspid: u32) {
+    let Some(container) = proc
+        .process
+        .pod
+        .as_mut()
+        .and_then(|pod| pod.container.as_mut())
+    else {
+        return;
+    };
+    if nspid == 1 || container.maybe_exec_probe {
+        return;
+    }
+    let (Some(exec_time), Some(start_time)) = (&proc.process.start_time, &container.start_time)
+    else {
+        return;
+    };
+    if !started_after_container(exec_time, start_time) {
+        return;
+    }
+
+    let Some(parent) = cache_get(&proc.process.parent_exec_id).await else {
+        return;
+    };
+    container.maybe_exec_session = is_runtime_exec_parent(&parent.process.binary);
+}
+
+// Lookups of an exec that missed their cache. The exec is held until they are
+// done, so that the first exec of a binary has them too.
+#[derive(Default)]
+pub struct Lookups {
+    binary_hash: Option<Pending<String>>,
+    passwd: Option<Pending<Arc<Passwd>>>,
+    local: Option<Pending<Local>>,
+}
+
+impl Lookups {
+    pub fn is_empty(&self) -> bool {
+        self.binary_hash.is_none() && self.passwd.is_none() && self.local.is_none()
+    }
+
+    async fn complete(self, proc: &mut ProcessInternal) {
+        let deadline = tokio::time::Instant::now() + LOOKUP_TIMEOUT;
+        if let Some(hash) = self.binary_hash {
+            proc.binary_hash = hash.wait(deadline).await.unwrap_or_default();
+        }
+        if let Some(passwd) = self.passwd {
+            if let Some(passwd) = passwd.wait(deadline).await {
+                enrich_user(proc, &passwd);
+            }
+        }
+        if let Some(local) = self.local {
+            if let Some(local) = local.wait(deadline).await {
+                enrich_local(proc, local);
+            }
+        }
+    }
+}
+
+// Sets the fields of the exec that are looked up in userspace when they are
+// cached, and returns the lookups to wait for otherwise
+fn start_lookups(proc: &mut ProcessInternal, ima: &MsgImaHash) -> Lookups {
+    let mut lookups = Lookups::default();
+    let pid = proc.process.pid.unwrap_or_default();
+    match binary_hash(pid, ima) {
+        Lookup::Cached(hash) => proc.binary_hash = hash,
+        Lookup::Pending(hash) => lookups.binary_hash = Some(hash),
+    }
+    match user_passwd(proc) {
+        Some(Lookup::Cached(passwd)) => enrich_user(proc, &passwd),
+        Some(Lookup::Pending(passwd)) => lookups.passwd = Some(passwd),
+        None => {}
+    }
+    // Without Kubernetes, the container or unit of the process instead of its pod
+    if !*ENABLE_K8S_API {
+        match get_local(proc) {
+            Some(Lookup::Cached(local)) => enrich_local(proc, local),
+            Some(Lookup::Pending(local)) => lookups.local = Some(local),
+            None => {}
+        }
+    }
+    lookups
 }
 
 pub async fn add_exec_event(
     event: &mut MsgExecveEvent,
     store: PodStore,
-) -> anyhow::Result<ProcessInternal> {
-    let proc: ProcessInternal = if event.cleanup_key.ktime == 0
+) -> anyhow::Result<(ProcessInternal, Lookups)> {
+    let mut proc: ProcessInternal = if event.cleanup_key.ktime == 0
         || (event.process.flags as u64 & msg_flags::EVENT_CLONE) != 0
     {
         // there is a case where we cannot find this entry in execve_map
@@ -233,10 +443,47 @@ pub async fn add_exec_event(
     } else {
         init_process_internal_exec(event, &event.cleanup_key.clone(), store)?
     };
+    mark_exec_session(&mut proc, event.process.nspid).await;
+    let lookups = start_lookups(&mut proc, &event.ima);
 
     cache_add(proc.clone()).await?;
 
-    Ok(proc)
+    Ok((proc, lookups))
+}
+
+// Whether the exec must be held until its pod is known or its lookups are done
+pub fn exec_pending(proc: &ProcessInternal, lookups: &Lookups, store: &PodStore) -> bool {
+    !lookups.is_empty() || pod_pending(proc, store)
+}
+
+// Whether the process runs in a container whose pod is not known yet
+fn pod_pending(proc: &ProcessInternal, store: &PodStore) -> bool {
+    *ENABLE_K8S_API && proc.process.pod.is_none() && podinfo::pod_pending(proc.cgrpid, store)
+}
+
+// Waits for the pod and the lookups of a held exec, and sets them in the cache
+pub async fn complete_exec(
+    mut proc: ProcessInternal,
+    lookups: Lookups,
+    nspid: u32,
+    store: PodStore,
+) -> ProcessInternal {
+    if pod_pending(&proc, &store) {
+        proc.process.pod = wait_pod_info(
+            proc.cgrpid,
+            &proc.process.binary,
+            &proc.process.arguments,
+            nspid,
+            store,
+            POD_WAIT_TIMEOUT,
+        )
+        .await;
+        mark_exec_session(&mut proc, nspid).await;
+    }
+    lookups.complete(&mut proc).await;
+
+    cache_update(&proc).await;
+    proc
 }
 
 pub fn print_struct_size() {
@@ -245,3 +492,49 @@ pub fn print_struct_size() {
     info!("MsgExecveEvent size: {}", mem::size_of::<MsgExecveEvent>());
     info!("MsgExit size: {}", mem::size_of::<MsgExit>());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_runtime_exec_parent() {
+        assert!(is_runtime_exec_parent("/usr/bin/runc"));
+        assert!(is_runtime_exec_parent("/usr/local/bin/crun"));
+        assert!(is_runtime_exec_parent("/usr/bin/conmon"));
+        assert!(is_runtime_exec_parent("/usr/bin/containerd-shim-runc-v2"));
+        assert!(!is_runtime_exec_parent("/bin/sh"));
+        assert!(!is_runtime_exec_parent("/usr/bin/containerd"));
+    }
+
+    #[test]
+    fn test_started_after_container() {
+        let at = |seconds| Timestamp { seconds, nanos: 0 };
+        // The init runs before the start time, which is truncated to the second
+        assert!(!started_after_container(&at(99), &at(100)));
+        assert!(!started_after_container(&at(101), &at(100)));
+        assert!(started_after_container(&at(102), &at(100)));
+    }
+
+    #[tokio::test]
+    async fn test_first_exec_has_binary_hash() {
+        use sha2::{Digest, Sha256};
+
+        std::env::set_var("EXPORT_BINARY_HASH", "1");
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let data = std::fs::read(format!("/proc/{}/exe", child.id())).unwrap();
+        let expected = format!("sha256:{:x}", Sha256::digest(&data));
+
+        let mut proc = ProcessInternal::default();
+        proc.process.pid = Some(child.id());
+        let lookups = start_lookups(&mut proc, &MsgImaHash::default());
+        lookups.complete(&mut proc).await;
+        let _ = child.kill();
+        let _ = child.wait();
+
+        assert_eq!(proc.binary_hash, expected);
+    }
+}
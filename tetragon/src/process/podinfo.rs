@@ -1,14 +1,29 @@
 use crate::api::{Container, Image, Pod};
 use crate::cgidmap;
+use crate::podhelpers::strip_runtime_prefix;
+use crate::process::args::args_to_string;
 use crate::watcher::PodStore;
+use k8s_openapi::api::core::v1::{ContainerStatus, Pod as K8sPod};
+use lru::LruCache;
+use prost_types::Timestamp;
 use regex::Regex;
-use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
 use tracing::*;
+
+// Pod info of the containers, with the resourceVersion of the pod it was
+// derived from, so that it is only rebuilt when the pod changes.
+static POD_INFO_CACHE: LazyLock<Mutex<LruCache<String, (String, Pod)>>> =
+    LazyLock::new(|| Mutex::new(LruCache::new(NonZeroUsize::new(1024).unwrap())));
+
+// Returns the pod info of the container of the cgroup, if its pod is already
+// known. Use pod_pending and wait_pod_info for the pods not known yet.
 pub fn get_pod_info(
     cgrpid: u64,
-    _binary: &str,
-    _args: &str,
-    _nspid: u32,
+    binary: &str,
+    args: &str,
+    nspid: u32,
     store: PodStore,
 ) -> Option<Pod> {
     debug!(
@@ -24,7 +39,7 @@ pub fn get_pod_info(
         return None;
     };
 
-    let Some(pod) = store.get_with_retry(&container_id, 20) else {
+    let Some(pod) = store.get(&container_id) else {
         debug!(
             "get_pod_info: No pod found for container ID: {}",
             container_id
@@ -32,30 +47,214 @@ pub fn get_pod_info(
         return None;
     };
 
-    // TODO: Populate fieds like workload, workload_kind, and pod_labels
-    Some(Pod {
+    Some(pod_info(&pod, &container_id, binary, args, nspid))
+}
+
+// Whether the cgroup is the one of a container whose pod is not known yet
+pub fn pod_pending(cgrpid: u64, store: &PodStore) -> bool {
+    cgidmap::get(cgrpid).is_some_and(|container_id| store.get(&container_id).is_none())
+}
+
+// Like get_pod_info, waiting for at most timeout until the pod is known
+pub async fn wait_pod_info(
+    cgrpid: u64,
+    binary: &str,
+    args: &str,
+    nspid: u32,
+    store: PodStore,
+    timeout: Duration,
+) -> Option<Pod> {
+    let container_id = cgidmap::get(cgrpid)?;
+    let Some(pod) = store.wait_for(&container_id, timeout).await else {
+        debug!(
+            "wait_pod_info: No pod found for container ID after {:?}: {}",
+            timeout, container_id
+        );
+        return None;
+    };
+
+    Some(pod_info(&pod, &container_id, binary, args, nspid))
+}
+
+fn pod_info(pod: &K8sPod, container_id: &str, binary: &str, args: &str, nspid: u32) -> Pod {
+    let mut pod_info = cached_pod_info(pod, container_id);
+    if let Some(container) = pod_info.container.as_mut() {
+        container.pid = Some(nspid);
+        container.maybe_exec_probe = maybe_exec_probe(pod, &container.name, binary, args);
+    }
+    pod_info
+}
+
+fn cached_pod_info(pod: &K8sPod, container_id: &str) -> Pod {
+    let version = pod.metadata.resource_version.clone().unwrap_or_default();
+
+    let mut cache = POD_INFO_CACHE.lock().unwrap();
+    if let Some((cached_version, pod_info)) = cache.get(container_id) {
+        if *cached_version == version {
+            return pod_info.clone();
+        }
+    }
+
+    let pod_info = build_pod_info(pod, container_id);
+    // The status of a new container may not be there yet, retry with the
+    // next version of the pod
+    let found = pod_info
+        .container
+        .as_ref()
+        .is_some_and(|c| !c.name.is_empty());
+    if found {
+        cache.put(container_id.to_string(), (version, pod_info.clone()));
+    }
+    pod_info
+}
+
+fn build_pod_info(pod: &K8sPod, container_id: &str) -> Pod {
+    let (workload, workload_kind) = workload_of(pod);
+    let container = find_container_status(pod, container_id).map_or_else(
+        || Container {
+            id: container_id.to_string(),
+            ..Default::default()
+        },
+        |cs| container_from_status(cs, container_id),
+    );
+
+    Pod {
         namespace: pod.metadata.namespace.clone().unwrap_or_default(),
-        workload: "".to_string(),
-        workload_kind: "".to_string(),
+        workload,
+        workload_kind,
         name: pod.metadata.name.clone().unwrap_or_default(),
-        pod_labels: HashMap::new(),
-        container: Some(Container {
-            id: "".to_string(),
-            pid: Some(0),
-            name: "".to_string(),
-            image: Some(Image {
-                id: "".to_string(),
-                name: "".to_string(),
-            }),
-            start_time: None,
-            maybe_exec_probe: false,
+        pod_labels: pod
+            .metadata
+            .labels
+            .as_ref()
+            .map(|labels| labels.clone().into_iter().collect())
+            .unwrap_or_default(),
+        container: Some(container),
+    }
+}
+
+fn find_container_status<'a>(pod: &'a K8sPod, container_id: &str) -> Option<&'a ContainerStatus> {
+    let status = pod.status.as_ref()?;
+    [
+        &status.container_statuses,
+        &status.init_container_statuses,
+        &status.ephemeral_container_statuses,
+    ]
+    .into_iter()
+    .filter_map(|statuses| statuses.as_ref())
+    .flatten()
+    .find(|cs| {
+        cs.container_id
+            .as_deref()
+            .is_some_and(|id| strip_runtime_prefix(id) == container_id)
+    })
+}
+
+fn container_from_status(cs: &ContainerStatus, container_id: &str) -> Container {
+    let start_time = cs
+        .state
+        .as_ref()
+        .and_then(|state| state.running.as_ref())
+        .and_then(|running| running.started_at.as_ref())
+        .map(|time| Timestamp {
+            seconds: time.0.timestamp(),
+            nanos: time.0.timestamp_subsec_nanos() as i32,
+        });
+
+    Container {
+        id: container_id.to_string(),
+        name: cs.name.clone(),
+        image: Some(Image {
+            id: cs.image_id.clone(),
+            name: cs.image.clone(),
         }),
+        start_time,
+        pid: None,
+        maybe_exec_probe: false,
+        maybe_exec_session: false,
+    }
+}
+
+// Returns whether binary and args match the exec command of one of the
+// liveness, readiness or startup probes of the container. The binary matches
+// the command by basename, like /bin/ls and ls.
+fn maybe_exec_probe(pod: &K8sPod, container_name: &str, binary: &str, args: &str) -> bool {
+    let Some(container) = pod
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.containers.iter().find(|c| c.name == container_name))
+    else {
+        return false;
+    };
+
+    let basename = |path: &str| path.rsplit('/').next().unwrap_or_default().to_string();
+    [
+        &container.liveness_probe,
+        &container.readiness_probe,
+        &container.startup_probe,
+    ]
+    .into_iter()
+    .filter_map(|probe| probe.as_ref()?.exec.as_ref()?.command.as_ref())
+    .any(|command| match command.split_first() {
+        Some((cmd, cmd_args)) => {
+            basename(cmd) == basename(binary) && args_to_string(cmd_args) == args
+        }
+        None => false,
     })
 }
 
+// CronJobs name their Jobs <cronjob>-<scheduled time in minutes since epoch>
+fn cronjob_of_job(name: &str) -> Option<&str> {
+    let (cronjob, time) = name.rsplit_once('-')?;
+    if cronjob.is_empty() || time.len() < 8 || !time.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(cronjob)
+}
+
+// Returns the workload of the pod and its kind, from the controller owner
+// reference. ReplicaSets and Jobs are followed up to the Deployment and the
+// CronJob which created them, from the suffixes these add to their names.
+fn workload_of(pod: &K8sPod) -> (String, String) {
+    let pod_name = pod.metadata.name.clone().unwrap_or_default();
+    let owners = pod.metadata.owner_references.as_deref().unwrap_or_default();
+    let Some(owner) = owners
+        .iter()
+        .find(|owner| owner.controller == Some(true))
+        .or(owners.first())
+    else {
+        return (pod_name, "Pod".to_string());
+    };
+
+    match owner.kind.as_str() {
+        // Deployments name their ReplicaSets <deployment>-<pod-template-hash>
+        "ReplicaSet" => {
+            let hash = pod
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get("pod-template-hash"));
+            match hash.and_then(|hash| owner.name.strip_suffix(&format!("-{}", hash))) {
+                Some(deployment) => (deployment.to_string(), "Deployment".to_string()),
+                None => (owner.name.clone(), owner.kind.clone()),
+            }
+        }
+        "Job" => match cronjob_of_job(&owner.name) {
+            Some(cronjob) => (cronjob.to_string(), "CronJob".to_string()),
+            None => (owner.name.clone(), owner.kind.clone()),
+        },
+        // Static pods are owned by their node
+        "Node" => (pod_name, "Pod".to_string()),
+        _ => (owner.name.clone(), owner.kind.clone()),
+    }
+}
+
 fn extract_hash(input: &str) -> Option<String> {
-    // TODO: Support containerd
-    let re = Regex::new(r"^crio-conmon-([0-9a-fA-F]{64})\.scope$").unwrap();
+    // crio-conmon-<ID>.scope, crio-<ID>.scope and cri-containerd-<ID>.scope with the
+    // systemd cgroup driver, <ID> with the cgroupfs one.
+    let re =
+        Regex::new(r"^(?:(?:crio-conmon|crio|cri-containerd)-)?([0-9a-fA-F]{64})(?:\.scope)?$")
+            .unwrap();
 
     re.captures(input)
         .and_then(|caps| caps.get(1))
@@ -65,6 +264,175 @@ fn extract_hash(input: &str) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use k8s_openapi::api::core::v1::{
+        Container as K8sContainer, ContainerState, ContainerStateRunning, ExecAction, PodSpec,
+        PodStatus, Probe,
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference, Time};
+
+    fn pod_owned_by(kind: &str, name: &str, labels: &[(&str, &str)]) -> K8sPod {
+        K8sPod {
+            metadata: ObjectMeta {
+                name: Some("pod".to_string()),
+                namespace: Some("default".to_string()),
+                labels: Some(
+                    labels
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                ),
+                owner_references: Some(vec![OwnerReference {
+                    kind: kind.to_string(),
+                    name: name.to_string(),
+                    controller: Some(true),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_workload_of() {
+        let test_cases = vec![
+            (
+                pod_owned_by(
+                    "ReplicaSet",
+                    "nginx-7c5ddbdf54",
+                    &[("pod-template-hash", "7c5ddbdf54")],
+                ),
+                ("nginx", "Deployment"),
+            ),
+            (
+                pod_owned_by("ReplicaSet", "nginx-rs", &[]),
+                ("nginx-rs", "ReplicaSet"),
+            ),
+            (
+                pod_owned_by("Job", "backup-28913400", &[]),
+                ("backup", "CronJob"),
+            ),
+            (pod_owned_by("Job", "migrate-2", &[]), ("migrate-2", "Job")),
+            (
+                pod_owned_by("DaemonSet", "cilium", &[]),
+                ("cilium", "DaemonSet"),
+            ),
+            (pod_owned_by("Node", "node1", &[]), ("pod", "Pod")),
+        ];
+        for (pod, (workload, kind)) in test_cases {
+            assert_eq!(workload_of(&pod), (workload.to_string(), kind.to_string()));
+        }
+
+        let pod = K8sPod {
+            metadata: ObjectMeta {
+                name: Some("standalone".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            workload_of(&pod),
+            ("standalone".to_string(), "Pod".to_string())
+        );
+    }
+
+    #[test]
+    fn test_maybe_exec_probe() {
+        let exec_probe = |command: &[&str]| Probe {
+            exec: Some(ExecAction {
+                command: Some(command.iter().map(|s| s.to_string()).collect()),
+            }),
+            ..Default::default()
+        };
+        let mut pod = pod_owned_by("DaemonSet", "agent", &[]);
+        pod.spec = Some(PodSpec {
+            containers: vec![K8sContainer {
+                name: "main".to_string(),
+                liveness_probe: Some(exec_probe(&["cat", "/tmp/healthy"])),
+                readiness_probe: Some(exec_probe(&["/bin/sh", "-c", "test -f /tmp/ready"])),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+
+        assert!(maybe_exec_probe(
+            &pod,
+            "main",
+            "/usr/bin/cat",
+            "/tmp/healthy"
+        ));
+        assert!(maybe_exec_probe(
+            &pod,
+            "main",
+            "/bin/sh",
+            "-c \"test -f /tmp/ready\""
+        ));
+        assert!(!maybe_exec_probe(
+            &pod,
+            "main",
+            "/usr/bin/cat",
+            "/etc/passwd"
+        ));
+        assert!(!maybe_exec_probe(
+            &pod,
+            "main",
+            "/usr/bin/ls",
+            "/tmp/healthy"
+        ));
+        assert!(!maybe_exec_probe(
+            &pod,
+            "sidecar",
+            "/usr/bin/cat",
+            "/tmp/healthy"
+        ));
+    }
+
+    #[test]
+    fn test_build_pod_info() {
+        let mut pod = pod_owned_by("DaemonSet", "agent", &[("app", "agent")]);
+        let started_at = chrono::DateTime::from_timestamp(1700000000, 0).unwrap();
+        pod.status = Some(PodStatus {
+            container_statuses: Some(vec![ContainerStatus {
+                name: "main".to_string(),
+                container_id: Some("containerd://abcd".to_string()),
+                image: "docker.io/library/agent:1.0".to_string(),
+                image_id: "docker.io/library/agent@sha256:1234".to_string(),
+                state: Some(ContainerState {
+                    running: Some(ContainerStateRunning {
+                        started_at: Some(Time(started_at)),
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+
+        let pod_info = build_pod_info(&pod, "abcd");
+        assert_eq!(pod_info.workload, "agent");
+        assert_eq!(pod_info.workload_kind, "DaemonSet");
+        assert_eq!(
+            pod_info.pod_labels.get("app").map(String::as_str),
+            Some("agent")
+        );
+        let container = pod_info.container.unwrap();
+        assert_eq!(container.name, "main");
+        assert_eq!(
+            container.image,
+            Some(Image {
+                id: "docker.io/library/agent@sha256:1234".to_string(),
+                name: "docker.io/library/agent:1.0".to_string(),
+            })
+        );
+        assert_eq!(container.start_time.map(|t| t.seconds), Some(1700000000));
+
+        // The status of the container is not there yet
+        let container = build_pod_info(&pod, "efgh").container.unwrap();
+        assert_eq!(
+            (container.id.as_str(), container.name.as_str()),
+            ("efgh", "")
+        );
+    }
 
     #[test]
     fn test_extract_hash() {
@@ -73,5 +441,15 @@ mod tests {
         let expected = "dc77e3758c764db61efd00260a92d34cc221e88ff46920b4bb616c4f17f734e1";
 
         assert_eq!(extract_hash(input), Some(expected.to_string()));
+
+        let input =
+            "cri-containerd-5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147.scope";
+        let expected = "5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147";
+        assert_eq!(extract_hash(input), Some(expected.to_string()));
+
+        let input = "5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147";
+        assert_eq!(extract_hash(input), Some(input.to_string()));
+
+        assert_eq!(extract_hash("kubepods-besteffort.slice"), None);
     }
 }
@@ -1,8 +1,22 @@
+use crate::api::Process as ApiProcess;
+use crate::cgidmap;
+use crate::cgroups::linux::{
+    get_cgroup_id_from_path, get_cgroup_mode, host_cgroup_root, CGROUP_CONTROLLERS,
+};
+use crate::cgroups::CgroupModeCode;
+use crate::process::args::{args_to_string, EXPORT_ARGV};
+use crate::process::cache::cache_add;
+use crate::process::local::resolve_local;
+use crate::process::podinfo::get_pod_info;
+use crate::process::{get_process_id, ProcessInternal};
 use crate::util::NamespaceType;
+use crate::watcher::{PodStore, ENABLE_K8S_API};
 use procfs::process::{Process, Task};
 use procfs::WithCurrentSystemInfo;
 use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use tetragon_common::bpf_cred::MsgCapabilities;
+use tetragon_common::flags::msg_flags;
 use tetragon_common::process::{Binary, ExecveMapValue, MsgExecveKey, MsgNs, BINARY_PATH_MAX_LEN};
 use tracing::*;
 
@@ -12,6 +26,7 @@ impl TryFrom<ProcessWrapper> for ExecveMapValue {
     type Error = anyhow::Error;
     fn try_from(p: ProcessWrapper) -> Result<Self, Self::Error> {
         let p = p.0;
+        let ppid = p.stat().map_or(0, |stat| stat.ppid);
         let namespaces = p.namespaces();
         let binary = if let Ok(path) = p.exe() {
             let path_str = path.to_string_lossy();
@@ -35,23 +50,19 @@ impl TryFrom<ProcessWrapper> for ExecveMapValue {
             key: MsgExecveKey {
                 pid: p.pid as u32,
                 pad: [0; 4],
-                ktime: p.stat().map_or(0, |stat| {
-                    stat.starttime().get().unwrap_or_default().timestamp() as u64
-                }),
+                ktime: start_ktime(p.pid),
             },
             pkey: MsgExecveKey {
-                pid: p.stat().map_or(0, |stat| stat.ppid as u32),
+                pid: ppid as u32,
                 pad: [0; 4],
-                ktime: Process::new(p.pid)?.stat().map_or(0, |stat| {
-                    stat.starttime().get().unwrap_or_default().timestamp() as u64
-                }),
+                ktime: start_ktime(ppid),
             },
             flags: 0,
             nspid: p
                 .status()
                 .ok()
-                .and_then(|status| status.nspgid.clone())
-                .and_then(|nspgid| nspgid.last().copied())
+                .and_then(|status| status.nspid.clone())
+                .and_then(|nspid| nspid.last().copied())
                 .map(|pid| pid as u32)
                 .unwrap_or(0),
             ns: MsgNs {
@@ -126,6 +137,13 @@ impl TryFrom<ProcessWrapper> for ExecveMapValue {
     }
 }
 
+// Start time of the process, used in its execve map key and exec id
+fn start_ktime(pid: i32) -> u64 {
+    Process::new(pid).and_then(|p| p.stat()).map_or(0, |stat| {
+        stat.starttime().get().unwrap_or_default().timestamp() as u64
+    })
+}
+
 pub type Thread = Task;
 pub struct ThreadWrapper(Thread);
 
@@ -172,8 +190,8 @@ impl TryFrom<ThreadWrapper> for ExecveMapValue {
             nspid: t
                 .status()
                 .ok()
-                .and_then(|status| status.nspgid.clone())
-                .and_then(|nspgid| nspgid.last().copied())
+                .and_then(|status| status.nspid.clone())
+                .and_then(|nspid| nspid.last().copied())
                 .map(|pid| pid as u32)
                 .unwrap_or(0),
             ns: MsgNs {
@@ -329,6 +347,137 @@ fn collect_execve_map_values() -> anyhow::Result<Vec<ExecveMapValue>> {
     Ok(execve_maps)
 }
 
+// Returns the path on the host of the cgroup of the process, from its cgroup v2
+// entry or, with cgroup v1, the one of the first CGROUP_CONTROLLERS mounted.
+fn process_cgroup_path(p: &Process, cg_root: &str) -> Option<PathBuf> {
+    if cg_root.is_empty() {
+        return None;
+    }
+    let cgroups = p.cgroups().ok()?.0;
+    let (dir, pathname) = if get_cgroup_mode() == CgroupModeCode::Unified {
+        let cg = cgroups.iter().find(|cg| cg.hierarchy == 0)?;
+        (String::new(), &cg.pathname)
+    } else {
+        let cg = CGROUP_CONTROLLERS.iter().find_map(|wanted| {
+            cgroups
+                .iter()
+                .find(|cg| cg.controllers.contains(&wanted.name))
+        })?;
+        (cg.controllers.join(","), &cg.pathname)
+    };
+
+    Some(
+        Path::new(cg_root)
+            .join(dir)
+            .join(pathname.trim_start_matches('/')),
+    )
+}
+
+// Returns the id of the container cgroup of a process in the cgroup at path,
+// which may be nested below it.
+fn container_cgroup_id(path: &Path, cg_root: &Path) -> Option<u64> {
+    path.ancestors()
+        .take_while(|dir| dir.starts_with(cg_root) && *dir != cg_root)
+        .filter_map(|dir| get_cgroup_id_from_path(&dir.to_string_lossy()).ok())
+        .find(|&id| cgidmap::get(id).is_some())
+}
+
+fn process_internal(
+    p: &Process,
+    cg_root: &str,
+    store: &PodStore,
+) -> anyhow::Result<ProcessInternal> {
+    let ppid = p.stat()?.ppid;
+    let pid = p.pid as u32;
+    let binary = p
+        .exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let cwd = p
+        .cwd()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let argv: Vec<String> = p
+        .cmdline()
+        .unwrap_or_default()
+        .into_iter()
+        .skip(1)
+        .collect();
+    let args = args_to_string(&argv);
+    let nspid = p
+        .status()
+        .ok()
+        .and_then(|status| status.nspid)
+        .and_then(|nspid| nspid.last().copied())
+        .map(|pid| pid as u32)
+        .unwrap_or(0);
+
+    let cgrpid = process_cgroup_path(p, cg_root)
+        .and_then(|path| container_cgroup_id(&path, Path::new(cg_root)));
+    let (docker, pod) = match cgrpid {
+        Some(id) => (
+            cgidmap::get(id).unwrap_or_default(),
+            get_pod_info(id, &binary, &args, nspid, store.clone()),
+        ),
+        None => (String::new(), None),
+    };
+
+    Ok(ProcessInternal {
+        process: ApiProcess {
+            pid: Some(pid),
+            tid: Some(pid),
+            uid: p.uid().ok(),
+            cwd,
+            binary,
+            arguments: args,
+            flags: msg_flags::EVENT_PROCFS.to_string(),
+            pod,
+            exec_id: get_process_id(pid, start_ktime(p.pid)),
+            docker,
+            parent_exec_id: get_process_id(ppid as u32, start_ktime(ppid)),
+            argv: if *EXPORT_ARGV { argv } else { Vec::new() },
+            ..Default::default()
+        },
+        cgrpid: cgrpid.unwrap_or(0),
+        refcnt: 1,
+        ..Default::default()
+    })
+}
+
+// Adds the processes running at startup to the process cache, with the pod
+// info resolved from the bootstrapped cgidmap. Waits for the pod informer to
+// sync, so that their pods are found.
+pub async fn add_initial_processes(store: PodStore) -> anyhow::Result<()> {
+    store.wait_until_ready().await?;
+
+    let procs = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<ProcessInternal>> {
+        let cg_root = host_cgroup_root().unwrap_or_default();
+        Ok(collect_processes()?
+            .iter()
+            .filter_map(|p| {
+                process_internal(p, &cg_root, &store)
+                    .inspect_err(|e| debug!("Error converting process {}: {}", p.pid, e))
+                    .ok()
+            })
+            .collect())
+    })
+    .await??;
+
+    let in_pods = procs.iter().filter(|p| p.process.pod.is_some()).count();
+    info!(
+        "Added {} initial processes to the cache, {} in pods",
+        procs.len(),
+        in_pods
+    );
+    for mut proc in procs {
+        if !*ENABLE_K8S_API {
+            resolve_local(&mut proc).await;
+        }
+        cache_add(proc).await?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,9 +1,8 @@
 use crate::api::{Capabilities, ProcessPrivilegesChanged, SecureBitsType};
 use tetragon_common::bpf_cred::MsgCapabilities;
-
-/* Execve extra flags */
-pub const EXECVE_SETUID: u32 = 0x01;
-pub const EXECVE_SETGID: u32 = 0x02;
+use tetragon_common::flags::secureexec_flags::{
+    EXECVE_FILE_CAPS, EXECVE_SETGID_ROOT, EXECVE_SETUID_ROOT,
+};
 
 fn get_capabilities_types(cap_int: u64) -> Vec<i32> {
     let mut caps = Vec::new();
@@ -72,17 +71,48 @@ pub fn get_privileges_changed_reasons(reasons: u32) -> Vec<i32> {
 
     let mut bits = Vec::new();
 
-    if reasons & ProcessPrivilegesChanged::PrivilegesRaisedExecFileCap as u32 != 0 {
+    if reasons & EXECVE_FILE_CAPS != 0 {
         bits.push(ProcessPrivilegesChanged::PrivilegesRaisedExecFileCap.into());
     }
 
-    if reasons & ProcessPrivilegesChanged::PrivilegesRaisedExecFileSetuid as u32 != 0 {
+    if reasons & EXECVE_SETUID_ROOT != 0 {
         bits.push(ProcessPrivilegesChanged::PrivilegesRaisedExecFileSetuid.into());
     }
 
-    if reasons & ProcessPrivilegesChanged::PrivilegesRaisedExecFileSetgid as u32 != 0 {
+    if reasons & EXECVE_SETGID_ROOT != 0 {
         bits.push(ProcessPrivilegesChanged::PrivilegesRaisedExecFileSetgid.into());
     }
 
     bits
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetragon_common::flags::secureexec_flags::{EXECVE_SETGID, EXECVE_SETUID};
+
+    #[test]
+    fn test_get_privileges_changed_reasons() {
+        assert!(get_privileges_changed_reasons(0).is_empty());
+
+        // setuid to a non root user is not a privilege elevation
+        assert!(get_privileges_changed_reasons(EXECVE_SETUID).is_empty());
+
+        let reasons = get_privileges_changed_reasons(
+            EXECVE_SETUID | EXECVE_SETUID_ROOT | EXECVE_SETGID | EXECVE_SETGID_ROOT,
+        );
+        assert_eq!(
+            reasons,
+            vec![
+                ProcessPrivilegesChanged::PrivilegesRaisedExecFileSetuid as i32,
+                ProcessPrivilegesChanged::PrivilegesRaisedExecFileSetgid as i32,
+            ]
+        );
+
+        let reasons = get_privileges_changed_reasons(EXECVE_FILE_CAPS);
+        assert_eq!(
+            reasons,
+            vec![ProcessPrivilegesChanged::PrivilegesRaisedExecFileCap as i32]
+        );
+    }
+}
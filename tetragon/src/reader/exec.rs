@@ -0,0 +1,67 @@
+// Signal numbers as defined in include/uapi/asm-generic/signal.h, which x86_64
+// and arm64 share.
+const SIGNAL_NAMES: [&str; 32] = [
+    "",
+    "SIGHUP",
+    "SIGINT",
+    "SIGQUIT",
+    "SIGILL",
+    "SIGTRAP",
+    "SIGABRT",
+    "SIGBUS",
+    "SIGFPE",
+    "SIGKILL",
+    "SIGUSR1",
+    "SIGSEGV",
+    "SIGUSR2",
+    "SIGPIPE",
+    "SIGALRM",
+    "SIGTERM",
+    "SIGSTKFLT",
+    "SIGCHLD",
+    "SIGCONT",
+    "SIGSTOP",
+    "SIGTSTP",
+    "SIGTTIN",
+    "SIGTTOU",
+    "SIGURG",
+    "SIGXCPU",
+    "SIGXFSZ",
+    "SIGVTALRM",
+    "SIGPROF",
+    "SIGWINCH",
+    "SIGIO",
+    "SIGPWR",
+    "SIGSYS",
+];
+
+// Returns the name of the signal, or an empty string if it's unknown.
+pub fn signal(sig: u32) -> String {
+    SIGNAL_NAMES
+        .get(sig as usize)
+        .map_or_else(String::new, |name| name.to_string())
+}
+
+// task->exit_code is encoded like the wait status: the exit status is in bits
+// 8-15, the terminating signal in bits 0-6 and bit 7 is set on a core dump.
+pub fn decode_exit_code(code: u32) -> (u32, String) {
+    ((code >> 8) & 0xff, signal(code & 0x7f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_exit_code() {
+        assert_eq!(decode_exit_code(0), (0, "".to_string()));
+        // exit(1)
+        assert_eq!(decode_exit_code(0x100), (1, "".to_string()));
+        // killed by SIGKILL
+        assert_eq!(decode_exit_code(9), (0, "SIGKILL".to_string()));
+        // SIGSEGV with a core dump
+        assert_eq!(decode_exit_code(0x8b), (0, "SIGSEGV".to_string()));
+        // real-time signals have no name
+        assert_eq!(decode_exit_code(34), (0, "".to_string()));
+    }
+}
@@ -1,4 +1,6 @@
 pub mod caps;
+pub mod exec;
 pub mod namespace;
 pub mod path;
 pub mod proc;
+pub mod user;
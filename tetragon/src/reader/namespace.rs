@@ -1,7 +1,8 @@
-use crate::api::{Namespace, Namespaces};
+use crate::api::{Namespace, Namespaces, UserNamespace};
 use crate::util::NamespaceType;
 use anyhow::Ok;
 use std::ffi::OsString;
+use tetragon_common::bpf_cred::MsgUserNamespace;
 use tetragon_common::process::MsgNs;
 
 use std::sync::LazyLock;
@@ -66,6 +67,25 @@ pub fn get_msg_namespaces(ns: MsgNs) -> anyhow::Result<Namespaces> {
     Ok(ret)
 }
 
+// A process is in the host user namespace only if it's at level 0, otherwise
+// uid 0 in its credentials is not the real root.
+pub fn get_msg_user_namespace(ns: &MsgUserNamespace) -> Option<UserNamespace> {
+    if ns.ns_inum == 0 {
+        return None;
+    }
+
+    let host_user_inum = HOST_NS.user.as_ref().map_or(0, |user| user.inum);
+    Some(UserNamespace {
+        level: Some(ns.level),
+        uid: Some(ns.uid),
+        gid: Some(ns.gid),
+        ns: Some(Namespace {
+            inum: ns.ns_inum,
+            is_host: ns.level == 0 && ns.ns_inum == host_user_inum,
+        }),
+    })
+}
+
 fn init_host_namespace() -> Namespaces {
     let namespaces = procfs::process::Process::new(1)
         .expect("fail to get pid 1")
@@ -0,0 +1,111 @@
+use crate::process::lookup::{Lookup, LookupCache};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
+use tracing::*;
+
+// How long a parsed /etc/passwd is used before checking whether it changed
+const PASSWD_RECHECK: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+pub struct Passwd {
+    mtime: Option<SystemTime>,
+    users: HashMap<u32, String>,
+}
+
+impl Passwd {
+    pub fn user_name(&self, uid: u32) -> Option<String> {
+        self.users.get(&uid).cloned()
+    }
+}
+
+// Parsed /etc/passwd keyed by mount namespace inum, so processes of the same
+// container share one entry. The file is parsed again when its mtime changes.
+static PASSWD_CACHE: LazyLock<LookupCache<u32, Arc<Passwd>>> =
+    LazyLock::new(|| LookupCache::new(256));
+
+// Returns the /etc/passwd seen by pid, read through /proc/<pid>/root so it
+// works for containers too. The file is read in a blocking task, the exec is
+// held on the Pending until it is done.
+pub fn get_passwd(pid: u32, mnt_inum: u32) -> Lookup<Arc<Passwd>> {
+    PASSWD_CACHE.get(mnt_inum, move || async move {
+        tokio::task::spawn_blocking(move || load_passwd(pid, mnt_inum))
+            .await
+            .unwrap_or_else(|_| (Arc::default(), Some(Duration::ZERO)))
+    })
+}
+
+fn load_passwd(pid: u32, mnt_inum: u32) -> (Arc<Passwd>, Option<Duration>) {
+    let path = format!("/proc/{}/root/etc/passwd", pid);
+    let Ok(mtime) = fs::metadata(&path).and_then(|m| m.modified()) else {
+        // Not kept, the process may only be gone
+        debug!("No /etc/passwd found for pid {}", pid);
+        return (Arc::default(), Some(Duration::ZERO));
+    };
+
+    if let Some(passwd) = PASSWD_CACHE.get_stale(&mnt_inum) {
+        if passwd.mtime == Some(mtime) {
+            return (passwd, Some(PASSWD_RECHECK));
+        }
+    }
+
+    let passwd = Passwd {
+        mtime: Some(mtime),
+        users: parse_ids(&fs::read_to_string(&path).unwrap_or_default()),
+    };
+    (Arc::new(passwd), Some(PASSWD_RECHECK))
+}
+
+// Lines of /etc/passwd are `name:password:UID:GID:GECOS:directory:shell`. The
+// first name of a uid is kept, like getpwuid does.
+fn parse_ids(content: &str) -> HashMap<u32, String> {
+    let mut ids = HashMap::new();
+    for line in content.lines().filter(|line| !line.starts_with('#')) {
+        let mut fields = line.split(':');
+        let name = fields.next();
+        let id = fields.nth(1).and_then(|id| id.parse::<u32>().ok());
+        let (Some(name), Some(id)) = (name, id) else {
+            continue;
+        };
+        ids.entry(id).or_insert_with(|| name.to_string());
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_passwd() {
+        let content = "\
+root:x:0:0:root:/root:/bin/bash
+# comment
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+broken
+app:x:1000:1000::/home/app:/bin/sh
+toor:x:0:0:root:/root:/bin/sh
+";
+        let users = parse_ids(content);
+        assert_eq!(users.len(), 3);
+        assert_eq!(users.get(&0).unwrap(), "root");
+        assert_eq!(users.get(&65534).unwrap(), "nobody");
+        assert_eq!(users.get(&1000).unwrap(), "app");
+    }
+
+    #[tokio::test]
+    async fn test_get_passwd() {
+        let pid = std::process::id();
+        let mnt_inum = u32::MAX;
+
+        // Read in a blocking task, then served from the cache
+        let Lookup::Pending(pending) = get_passwd(pid, mnt_inum) else {
+            panic!("read before the lookup");
+        };
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        let passwd = pending.wait(deadline).await.unwrap();
+        assert_eq!(passwd.user_name(0).as_deref(), Some("root"));
+        assert!(matches!(get_passwd(pid, mnt_inum), Lookup::Cached(_)));
+    }
+}
@@ -1,48 +1,58 @@
 use crate::api;
 use crate::cgroups;
+use crate::watcher::PodStore;
+use k8s_openapi::api::core::v1::Pod;
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use tracing::*;
 
+const CGROUP_MOUNT: &str = "/sys/fs/cgroup";
 const UID_STRING_LEN: usize = "00000000-0000-0000-0000-000000000000".len();
+// Set by the kubelet on the containers of static pods
+const CONFIG_HASH_ANNOTATION: &str = "kubernetes.io/config.hash";
+
+// The hook may run before the informer sees the pod
+const FIND_POD_RETRIES: usize = 5;
+const FIND_POD_TIMEOUT: Duration = Duration::from_millis(10);
 
 pub struct CreateContainerArg {
     req: api::CreateContainer,
-    // watcher: Arc<dyn watcher::PodAccessor>,
+    watcher: Option<PodStore>,
 
-    // cached values
-    cgroup_id: Option<u64>,
-    pod: Option<k8s_openapi::api::core::v1::Pod>,
-    host_cgroup_path: String,
+    // cached values, shared by the callbacks running concurrently
+    cgroup_id: OnceLock<u64>,
+    pod: OnceLock<Arc<Pod>>,
+    host_cgroup_path: OnceLock<String>,
 }
 
 impl CreateContainerArg {
-    // TODO: support watcher
-    // pub fn new(req: api::CreateContainer, watcher) -> Self {
-    pub fn new(req: api::CreateContainer) -> Self {
+    pub fn new(req: api::CreateContainer, watcher: Option<PodStore>) -> Self {
         Self {
             req,
-            // watcher,
-            cgroup_id: None,
-            pod: None,
-            host_cgroup_path: String::new(),
+            watcher,
+            cgroup_id: OnceLock::new(),
+            pod: OnceLock::new(),
+            host_cgroup_path: OnceLock::new(),
         }
     }
 
-    pub fn host_cgroup_path(&mut self) -> Result<String, std::io::Error> {
-        if self.host_cgroup_path.is_empty() {
-            let cg_path = &self.req.cgroups_path;
-            let cg_root = cgroups::linux::host_cgroup_root()?;
-            self.host_cgroup_path = Path::new(&cg_root)
-                .join(cg_path)
-                .to_string_lossy()
-                .into_owned();
+    pub fn host_cgroup_path(&self) -> Result<String, std::io::Error> {
+        if let Some(path) = self.host_cgroup_path.get() {
+            return Ok(path.clone());
         }
-        info!("host cgroup path: {}", self.host_cgroup_path);
-        Ok(self.host_cgroup_path.clone())
+
+        let cg_root = cgroups::linux::host_cgroup_root()?;
+        let path = self
+            .host_cgroup_path
+            .get_or_init(|| host_cgroup_path(&cg_root, &self.req.cgroups_path));
+        info!("host cgroup path: {}", path);
+        Ok(path.clone())
     }
 
-    pub fn cgroup_id(&mut self) -> Result<u64, std::io::Error> {
-        if let Some(id) = self.cgroup_id {
+    pub fn cgroup_id(&self) -> Result<u64, std::io::Error> {
+        if let Some(&id) = self.cgroup_id.get() {
             return Ok(id);
         }
 
@@ -54,78 +64,204 @@ impl CreateContainerArg {
         let path = self.host_cgroup_path()?;
         let cg_id = cgroups::linux::get_cgroup_id_from_sub_cgroup(&path)?;
 
-        self.cgroup_id = Some(cg_id);
         info!("cgroup id: {}", cg_id);
-        Ok(cg_id)
+        Ok(*self.cgroup_id.get_or_init(|| cg_id))
     }
 
     pub fn pod_id(&self) -> String {
         if !self.req.pod_uid.is_empty() {
             return self.req.pod_uid.clone();
         }
-        pod_id_from_cgroup_path(&self.req.cgroups_path)
+        pod_id_from_cgroup_path(&normalize_cgroup_path(&self.req.cgroups_path))
     }
 
     pub fn container_id(&self) -> String {
         if !self.req.container_id.is_empty() {
             return self.req.container_id.clone();
         }
-        container_id_from_cgroup_path(&self.req.cgroups_path)
+        container_id_from_cgroup_path(&normalize_cgroup_path(&self.req.cgroups_path))
+    }
+
+    // Static pods are created by the kubelet from files, and are known to the
+    // API server by their mirror pod
+    pub fn is_static_pod(&self) -> bool {
+        self.req.annotations.contains_key(CONFIG_HASH_ANNOTATION)
     }
 
-    pub fn pod(&mut self) -> Result<&k8s_openapi::api::core::v1::Pod, std::io::Error> {
-        if self.pod.is_some() {
-            return Ok(self.pod.as_ref().unwrap());
+    pub async fn pod(&self) -> Result<Arc<Pod>, std::io::Error> {
+        if let Some(pod) = self.pod.get() {
+            return Ok(pod.clone());
         }
 
-        let pod = if let Some(h) = self.req.annotations.get("kubernetes.io/config.hash") {
-            // NB: this is a static pod, so we need to find its mirror in the API server
-            self.find_mirror_pod(h)?
-        } else {
-            self.find_pod()?
+        let pod = match self.req.annotations.get(CONFIG_HASH_ANNOTATION) {
+            Some(hash) => self.find_mirror_pod(hash).await?,
+            None => self.find_pod().await?,
         };
+        Ok(self.pod.get_or_init(|| pod).clone())
+    }
 
-        self.pod = Some(pod);
-        Ok(self.pod.as_ref().unwrap())
-    }
-
-    fn find_mirror_pod(
-        &self,
-        hash: &str,
-    ) -> Result<k8s_openapi::api::core::v1::Pod, std::io::Error> {
-        // TODO: implement using watcher
-        // retry(5, Duration::from_millis(10), || {
-        //     self.watcher.find_mirror_pod(hash)
-        // })
-        let dummy_pod = k8s_openapi::api::core::v1::Pod {
-            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
-                name: Some(format!("mirror-{}", hash)),
-                ..Default::default()
-            },
-            ..Default::default()
-        };
-        Ok(dummy_pod)
+    fn watcher(&self) -> Result<&PodStore, std::io::Error> {
+        self.watcher
+            .as_ref()
+            .ok_or_else(|| std::io::Error::other("no pod watcher in runtime hooks"))
     }
 
-    fn find_pod(&self) -> Result<k8s_openapi::api::core::v1::Pod, std::io::Error> {
-        // TODO: implement using watcher
-        // let pod_id = self.pod_id()?;
+    async fn find_mirror_pod(&self, hash: &str) -> Result<Arc<Pod>, std::io::Error> {
+        let watcher = self.watcher()?;
+        retry(FIND_POD_RETRIES, FIND_POD_TIMEOUT, || {
+            watcher.find_mirror_pod(hash)
+        })
+        .await
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("mirror pod with hash {} not found", hash),
+            )
+        })
+    }
 
-        // retry(5, Duration::from_millis(10), || {
-        //     self.watcher.find_pod(&pod_id)
-        // })
-        let dummy_pod = k8s_openapi::api::core::v1::Pod {
-            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
-                name: Some("findpod".to_string()),
-                ..Default::default()
-            },
-            ..Default::default()
-        };
-        Ok(dummy_pod)
+    async fn find_pod(&self) -> Result<Arc<Pod>, std::io::Error> {
+        let watcher = self.watcher()?;
+        let pod_id = self.pod_id();
+        retry(FIND_POD_RETRIES, FIND_POD_TIMEOUT, || {
+            watcher.find_pod(&pod_id)
+        })
+        .await
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("pod {} not found", pod_id),
+            )
+        })
+    }
+}
+
+// Argument of the start, stop and delete container hooks
+#[derive(Debug, Clone, Default)]
+pub struct ContainerArg {
+    pub cgroups_path: String,
+    pub annotations: HashMap<String, String>,
+    pub container_id: String,
+    pub pod_uid: String,
+}
+
+impl ContainerArg {
+    pub fn pod_id(&self) -> String {
+        if !self.pod_uid.is_empty() {
+            return self.pod_uid.clone();
+        }
+        pod_id_from_cgroup_path(&normalize_cgroup_path(&self.cgroups_path))
+    }
+
+    pub fn container_id(&self) -> String {
+        if !self.container_id.is_empty() {
+            return self.container_id.clone();
+        }
+        container_id_from_cgroup_path(&normalize_cgroup_path(&self.cgroups_path))
+    }
+}
+
+impl From<api::StartContainer> for ContainerArg {
+    fn from(req: api::StartContainer) -> Self {
+        Self {
+            cgroups_path: req.cgroups_path,
+            annotations: req.annotations,
+            container_id: req.container_id,
+            pod_uid: req.pod_uid,
+        }
+    }
+}
+
+impl From<api::StopContainer> for ContainerArg {
+    fn from(req: api::StopContainer) -> Self {
+        Self {
+            cgroups_path: req.cgroups_path,
+            annotations: req.annotations,
+            container_id: req.container_id,
+            pod_uid: req.pod_uid,
+        }
+    }
+}
+
+impl From<api::DeleteContainer> for ContainerArg {
+    fn from(req: api::DeleteContainer) -> Self {
+        Self {
+            cgroups_path: req.cgroups_path,
+            annotations: req.annotations,
+            container_id: req.container_id,
+            pod_uid: req.pod_uid,
+        }
+    }
+}
+
+// Calls f until it returns a value, at most n_retries + 1 times
+async fn retry<R>(
+    n_retries: usize,
+    timeout: Duration,
+    mut f: impl FnMut() -> Option<R>,
+) -> Option<R> {
+    for i in 0..=n_retries {
+        if let Some(val) = f() {
+            return Some(val);
+        }
+        if i < n_retries {
+            tokio::time::sleep(timeout).await;
+        }
     }
+    None
 }
 
-fn pod_id_from_cgroup_path(p: &str) -> String {
+// Expands a systemd slice like a-b-c.slice into a.slice/a-b.slice/a-b-c.slice
+fn expand_slice(slice: &str) -> Option<String> {
+    let name = slice.strip_suffix(".slice")?;
+    if name == "-" {
+        return Some(String::new());
+    }
+
+    let mut path = String::new();
+    let mut prefix = String::new();
+    for component in name.split('-') {
+        if component.is_empty() || component.contains('/') {
+            return None;
+        }
+        path.push_str(&format!("/{}{}.slice", prefix, component));
+        prefix.push_str(&format!("{}-", component));
+    }
+    Some(path)
+}
+
+// With the systemd cgroup driver, both containerd and CRI-O set cgroupsPath in
+// the OCI spec as `slice:prefix:name`, e.g.
+// kubepods-besteffort-pod<uid>.slice:cri-containerd:<id>, which systemd
+// creates as <slice path>/<prefix>-<name>.scope. Other paths are returned as is.
+fn normalize_cgroup_path(p: &str) -> String {
+    let fields: Vec<&str> = p.split(':').collect();
+    let [slice, prefix, name] = fields[..] else {
+        return p.to_string();
+    };
+    let Some(slice_path) = expand_slice(slice) else {
+        return p.to_string();
+    };
+
+    if prefix.is_empty() {
+        format!("{}/{}", slice_path, name)
+    } else {
+        format!("{}/{}-{}.scope", slice_path, prefix, name)
+    }
+}
+
+// Returns the path of the cgroup below cg_root from the cgroupsPath of an OCI spec
+pub(crate) fn host_cgroup_path(cg_root: &str, cgroups_path: &str) -> String {
+    let cg_path = normalize_cgroup_path(cgroups_path);
+    // Hooks may send the path under the cgroup mount point already
+    let cg_path = cg_path.strip_prefix(CGROUP_MOUNT).unwrap_or(&cg_path);
+    Path::new(cg_root)
+        .join(cg_path.trim_start_matches('/'))
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub(crate) fn pod_id_from_cgroup_path(p: &str) -> String {
     let pod_path = Path::new(p).parent().unwrap_or(Path::new(""));
     let mut pod_id_str = pod_path
         .file_name()
@@ -145,14 +281,15 @@ fn pod_id_from_cgroup_path(p: &str) -> String {
     pod_id_str
 }
 
-fn container_id_from_cgroup_path(p: &str) -> String {
+pub(crate) fn container_id_from_cgroup_path(p: &str) -> String {
     let mut container_id = Path::new(p)
         .file_name()
         .unwrap_or_default()
         .to_string_lossy()
         .into_owned();
 
-    // crio has cgroups paths such as crio-<ID> and crio-conmon-<ID>. Strip those prefixes.
+    // crio has cgroups paths such as crio-<ID> and crio-conmon-<ID>, and containerd
+    // cri-containerd-<ID>. Strip those prefixes.
     if let Some(idx) = container_id.rfind('-') {
         container_id = container_id[idx + 1..].to_string();
     }
@@ -164,32 +301,82 @@ fn container_id_from_cgroup_path(p: &str) -> String {
     container_id
 }
 
-// fn retry<R, F, E>(n_retries: usize, timeout: Duration, mut f: F) -> Result<R, E>
-// where
-//     F: FnMut() -> Result<R, E>,
-//     E: std::fmt::Display,
-// {
-//     let mut last_err: Option<E> = None;
-
-//     for i in 0..=n_retries {
-//         match f() {
-//             Ok(val) => return Ok(val),
-//             Err(e) => {
-//                 last_err = Some(e);
-
-//                 if i < n_retries {
-//                     sleep(timeout);
-//                 }
-//             }
-//         }
-//     }
-
-//     Err(last_err.unwrap())
-// }
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::watcher::{index::CONFIG_MIRROR_ANNOTATION, pod_informer};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[tokio::test]
+    async fn test_find_pod() {
+        let (store, _informer) = pod_informer();
+        let pod = |name: &str, uid: &str, annotations: BTreeMap<String, String>| {
+            Arc::new(Pod {
+                metadata: ObjectMeta {
+                    name: Some(name.to_string()),
+                    uid: Some(uid.to_string()),
+                    annotations: Some(annotations),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        store.index.insert(pod(
+            "nginx",
+            "05e102bf-8744-4942-a241-9b6f07983a53",
+            BTreeMap::new(),
+        ));
+        // Mirror pods may only be in the cluster-wide cache
+        store.cluster_index.insert(pod(
+            "etcd-node1",
+            "897277d4-5e6f-4999-a976-b8340e8d075e",
+            BTreeMap::from([(CONFIG_MIRROR_ANNOTATION.to_string(), "abcd".to_string())]),
+        ));
+
+        let arg = CreateContainerArg::new(
+            api::CreateContainer {
+                pod_uid: "05e102bf-8744-4942-a241-9b6f07983a53".to_string(),
+                ..Default::default()
+            },
+            Some(store.clone()),
+        );
+        assert!(!arg.is_static_pod());
+        assert_eq!(
+            arg.pod().await.unwrap().metadata.name.as_deref(),
+            Some("nginx")
+        );
+
+        // The uid of a static pod is not the one of its mirror pod
+        let arg = CreateContainerArg::new(
+            api::CreateContainer {
+                pod_uid: "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0".to_string(),
+                annotations: HashMap::from([(
+                    CONFIG_HASH_ANNOTATION.to_string(),
+                    "abcd".to_string(),
+                )]),
+                ..Default::default()
+            },
+            Some(store.clone()),
+        );
+        assert!(arg.is_static_pod());
+        assert_eq!(
+            arg.pod().await.unwrap().metadata.name.as_deref(),
+            Some("etcd-node1")
+        );
+
+        let arg = CreateContainerArg::new(
+            api::CreateContainer {
+                pod_uid: "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0".to_string(),
+                ..Default::default()
+            },
+            Some(store),
+        );
+        assert_eq!(
+            arg.pod().await.unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+    }
 
     #[test]
     fn test_pod_id_from_cgroup_path() {
@@ -213,6 +400,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_normalize_cgroup_path() {
+        let test_cases = vec![
+            // containerd, systemd cgroup driver
+            (
+                "kubepods-besteffort-pod3b673e1d_289e_4210_8ceb_5a253b48d390.slice:cri-containerd:5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147",
+                "/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod3b673e1d_289e_4210_8ceb_5a253b48d390.slice/cri-containerd-5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147.scope",
+            ),
+            (
+                "kubepods-pod9c4a0f3e_5b1d_4e4a_9a55_2c1d3b7f7e10.slice:cri-containerd:0e4b8a1c7d3f2a9b6c5d4e3f2a1b0c9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b",
+                "/kubepods.slice/kubepods-pod9c4a0f3e_5b1d_4e4a_9a55_2c1d3b7f7e10.slice/cri-containerd-0e4b8a1c7d3f2a9b6c5d4e3f2a1b0c9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b.scope",
+            ),
+            // CRI-O, systemd cgroup driver
+            (
+                "kubepods-besteffort-podcbe1997c_18fc_4588_8499_605e8807a30f.slice:crio:51d5adf740c79d44a315d8bdd03a91c22b9af4b692d2764b1133e63dcdd2670b",
+                "/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-podcbe1997c_18fc_4588_8499_605e8807a30f.slice/crio-51d5adf740c79d44a315d8bdd03a91c22b9af4b692d2764b1133e63dcdd2670b.scope",
+            ),
+            // containerd, cgroupfs driver
+            (
+                "/kubepods/burstable/pod2f1e8a6b-3c4d-4e5f-8a9b-0c1d2e3f4a5b/7b2c4d6e8f0a1b3c5d7e9f1a2b4c6d8e0f1a3b5c7d9e1f2a4b6c8d0e2f4a6b8c",
+                "/kubepods/burstable/pod2f1e8a6b-3c4d-4e5f-8a9b-0c1d2e3f4a5b/7b2c4d6e8f0a1b3c5d7e9f1a2b4c6d8e0f1a3b5c7d9e1f2a4b6c8d0e2f4a6b8c",
+            ),
+        ];
+
+        for (path, expected) in test_cases {
+            assert_eq!(normalize_cgroup_path(path), expected);
+        }
+    }
+
+    #[test]
+    fn test_ids_from_systemd_cgroup_path() {
+        let path = normalize_cgroup_path("kubepods-burstable-pod4c9f1974_5c46_44c2_b42f_3bbf0e98eef9.slice:cri-containerd:bacb920470900725e0aa7d914fee5eb0854315448b024b6b8420ad8429c607ba");
+        assert_eq!(
+            pod_id_from_cgroup_path(&path),
+            "4c9f1974_5c46_44c2_b42f_3bbf0e98eef9"
+        );
+        assert_eq!(
+            container_id_from_cgroup_path(&path),
+            "bacb920470900725e0aa7d914fee5eb0854315448b024b6b8420ad8429c607ba"
+        );
+    }
+
     #[test]
     fn test_container_id_from_cgroup_path() {
         let test_cases = vec![
@@ -232,6 +461,10 @@ mod tests {
                 "kubepods-besteffort.slice/kubepods-besteffort-pod3b673e1d_289e_4210_8ceb_5a253b48d390.slice/cri-containerd-5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147.scope",
                 "5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147",
             ),
+            (
+                "/sys/fs/cgroup/kubepods/burstable/pod2f1e8a6b-3c4d-4e5f-8a9b-0c1d2e3f4a5b/7b2c4d6e8f0a1b3c5d7e9f1a2b4c6d8e0f1a3b5c7d9e1f2a4b6c8d0e2f4a6b8c",
+                "7b2c4d6e8f0a1b3c5d7e9f1a2b4c6d8e0f1a3b5c7d9e1f2a4b6c8d0e2f4a6b8c",
+            ),
         ];
 
         for (path, expected_id) in test_cases {
@@ -1,19 +1,25 @@
 pub mod args;
+pub mod oci;
 pub mod runner;
 
 use crate::api::RuntimeHookRequest;
 use crate::cgidmap;
+use crate::watcher::PodStore;
+use args::{ContainerArg, CreateContainerArg};
 use runner::Runner;
-use std::sync::{LazyLock, Mutex};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
 use thiserror::Error;
 
-pub(crate) static GLOBAL_RUNNER: LazyLock<Mutex<Runner>> =
-    LazyLock::new(|| Mutex::new(Runner::with_watcher()));
+// Only held to register hooks or to copy the runner, never across callbacks
+pub(crate) static GLOBAL_RUNNER: LazyLock<RwLock<Runner>> =
+    LazyLock::new(|| RwLock::new(Runner::default()));
 
 // TODO: refactor more smart initialization
-pub fn init_runner() -> &'static Mutex<Runner> {
+pub fn init_runner(watcher: PodStore) -> &'static RwLock<Runner> {
     let runner = &GLOBAL_RUNNER;
-    cgidmap::rthooks::register_callback();
+    runner.write().unwrap().set_watcher(watcher);
+    cgidmap::rthooks::register_hook();
     runner
 }
 
@@ -25,8 +31,11 @@ pub enum RtHookError {
     #[error("callback registration failed: {0}")]
     RegistrationError(String),
 
-    #[error("some hooks failed: {0}")]
-    RunHooksError(String),
+    #[error("callback {0} timed out after {1:?}")]
+    Timeout(String, Duration),
+
+    #[error("some hooks failed: {}", join_errors(.0))]
+    RunHooksError(Vec<RtHookError>),
 
     #[error("event type is not supported: {0}")]
     UnsupportedEvent(String),
@@ -35,19 +44,44 @@ pub enum RtHookError {
     Other(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
 
-// TODO: refactor using trait or builder pattern
-pub type CreateContainerCallback =
-    Box<dyn Fn(&mut args::CreateContainerArg) -> Result<(), RtHookError> + Send + Sync>;
-pub struct Callbacks {
-    pub create_container: CreateContainerCallback,
+fn join_errors(errors: &[RtHookError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// Callbacks of the container lifecycle events sent by the runtime hooks. The
+// callbacks of a hook run concurrently with the ones of the other hooks, so
+// events it does not handle default to doing nothing.
+#[tonic::async_trait]
+pub trait RuntimeHook: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn create_container(&self, _arg: &CreateContainerArg) -> Result<(), RtHookError> {
+        Ok(())
+    }
+
+    async fn start_container(&self, _arg: &ContainerArg) -> Result<(), RtHookError> {
+        Ok(())
+    }
+
+    async fn stop_container(&self, _arg: &ContainerArg) -> Result<(), RtHookError> {
+        Ok(())
+    }
+
+    async fn delete_container(&self, _arg: &ContainerArg) -> Result<(), RtHookError> {
+        Ok(())
+    }
 }
 
-pub fn register_callbacks_at_init(callbacks: Callbacks) {
-    let mut global_runner = GLOBAL_RUNNER.lock().unwrap();
-    global_runner.register_callback(callbacks);
+pub fn register_hook_at_init(hook: Arc<dyn RuntimeHook>) {
+    let mut global_runner = GLOBAL_RUNNER.write().unwrap();
+    global_runner.register_hook(hook);
 }
 
-pub fn run_hooks(request: &RuntimeHookRequest) -> Result<(), RtHookError> {
-    let global_runner = GLOBAL_RUNNER.lock().unwrap();
-    global_runner.run_hooks(request)
+pub async fn run_hooks(request: &RuntimeHookRequest) -> Result<(), RtHookError> {
+    let runner = GLOBAL_RUNNER.read().unwrap().clone();
+    runner.run_hooks(request).await
 }
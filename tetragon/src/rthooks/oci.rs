@@ -0,0 +1,222 @@
+use crate::api::{
+    runtime_hook_request::Event, CreateContainer, DeleteContainer, RuntimeHookRequest,
+    StartContainer,
+};
+use anyhow::Context as _;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+// CRI-O and containerd set the pod metadata in different annotations
+const CONTAINER_NAME_ANNOTATIONS: [&str; 2] = [
+    "io.kubernetes.container.name",
+    "io.kubernetes.cri.container-name",
+];
+const POD_NAME_ANNOTATIONS: [&str; 2] =
+    ["io.kubernetes.pod.name", "io.kubernetes.cri.sandbox-name"];
+const POD_UID_ANNOTATIONS: [&str; 2] = ["io.kubernetes.pod.uid", "io.kubernetes.cri.sandbox-uid"];
+const POD_NAMESPACE_ANNOTATIONS: [&str; 2] = [
+    "io.kubernetes.pod.namespace",
+    "io.kubernetes.cri.sandbox-namespace",
+];
+
+// State of the container that the runtime writes to the stdin of the hooks:
+// https://github.com/opencontainers/runtime-spec/blob/main/runtime.md#state
+#[derive(Debug, Deserialize)]
+pub struct State {
+    pub id: String,
+    pub bundle: String,
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+// Only the fields we need from the config.json of the bundle
+#[derive(Debug, Default, Deserialize)]
+pub struct Spec {
+    #[serde(default)]
+    pub root: Option<Root>,
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+    #[serde(default)]
+    pub linux: Option<Linux>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Root {
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Linux {
+    #[serde(rename = "cgroupsPath", default)]
+    pub cgroups_path: String,
+}
+
+// OCI hooks the binary can run as, named after the stages of the runtime spec.
+// There is no stage for StopContainer: poststop runs after the container is
+// deleted, so the stop events only come from NRI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookType {
+    CreateContainer,
+    StartContainer,
+    DeleteContainer,
+}
+
+impl HookType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "createRuntime" | "createContainer" | "prestart" => Some(Self::CreateContainer),
+            "startContainer" | "poststart" => Some(Self::StartContainer),
+            "poststop" => Some(Self::DeleteContainer),
+            _ => None,
+        }
+    }
+}
+
+pub fn read_state(input: impl std::io::Read) -> anyhow::Result<State> {
+    serde_json::from_reader(input).context("failed to parse the container state")
+}
+
+pub fn read_spec(bundle: &str) -> anyhow::Result<Spec> {
+    let path = Path::new(bundle).join("config.json");
+    let data = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&data).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn annotation(annotations: &HashMap<String, String>, keys: &[&str]) -> String {
+    keys.iter()
+        .find_map(|key| annotations.get(*key))
+        .cloned()
+        .unwrap_or_default()
+}
+
+// Builds the request sent to the agent from the state and the spec of the
+// container. The spec has the annotations of the runtime, the ones of the state
+// are only used when it has none.
+pub fn hook_request(hook: HookType, state: &State, spec: &Spec) -> RuntimeHookRequest {
+    let annotations = if spec.annotations.is_empty() {
+        state.annotations.clone()
+    } else {
+        spec.annotations.clone()
+    };
+    let cgroups_path = spec
+        .linux
+        .as_ref()
+        .map(|linux| linux.cgroups_path.clone())
+        .unwrap_or_default();
+    let container_id = state.id.clone();
+    let pod_uid = annotation(&annotations, &POD_UID_ANNOTATIONS);
+
+    let event = match hook {
+        HookType::CreateContainer => {
+            // The root path is relative to the bundle unless it is absolute
+            let root_dir = spec
+                .root
+                .as_ref()
+                .map(|root| Path::new(&state.bundle).join(&root.path))
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            Event::CreateContainer(CreateContainer {
+                cgroups_path,
+                root_dir,
+                container_name: annotation(&annotations, &CONTAINER_NAME_ANNOTATIONS),
+                container_id,
+                pod_name: annotation(&annotations, &POD_NAME_ANNOTATIONS),
+                pod_uid,
+                pod_namespace: annotation(&annotations, &POD_NAMESPACE_ANNOTATIONS),
+                annotations,
+            })
+        }
+        HookType::StartContainer => Event::StartContainer(StartContainer {
+            cgroups_path,
+            container_id,
+            pod_uid,
+            annotations,
+            ..Default::default()
+        }),
+        HookType::DeleteContainer => Event::DeleteContainer(DeleteContainer {
+            cgroups_path,
+            container_id,
+            pod_uid,
+            annotations,
+            ..Default::default()
+        }),
+    };
+
+    RuntimeHookRequest { event: Some(event) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATE: &str = r#"{
+        "ociVersion": "1.0.2",
+        "id": "5da35096936fefa0c7a7280a439fb8c680568820a20d410c7b9e30955d88a147",
+        "status": "creating",
+        "pid": 4422,
+        "bundle": "/run/containerd/io.containerd.runtime.v2.task/k8s.io/5da35096",
+        "annotations": {"io.kubernetes.cri.container-name": "from-state"}
+    }"#;
+
+    const SPEC: &str = r#"{
+        "ociVersion": "1.0.2",
+        "root": {"path": "rootfs"},
+        "annotations": {
+            "io.kubernetes.cri.container-name": "nginx",
+            "io.kubernetes.cri.sandbox-name": "nginx-pod",
+            "io.kubernetes.cri.sandbox-namespace": "default",
+            "io.kubernetes.cri.sandbox-uid": "3b673e1d-289e-4210-8ceb-5a253b48d390"
+        },
+        "linux": {
+            "cgroupsPath": "kubepods-besteffort-pod3b673e1d_289e_4210_8ceb_5a253b48d390.slice:cri-containerd:5da35096"
+        }
+    }"#;
+
+    #[test]
+    fn test_hook_request() {
+        let state = read_state(STATE.as_bytes()).unwrap();
+        let spec: Spec = serde_json::from_str(SPEC).unwrap();
+
+        let request = hook_request(HookType::CreateContainer, &state, &spec);
+        let Some(Event::CreateContainer(req)) = request.event else {
+            panic!("expected a create container event");
+        };
+        assert_eq!(req.container_id, state.id);
+        assert_eq!(req.container_name, "nginx");
+        assert_eq!(req.pod_name, "nginx-pod");
+        assert_eq!(req.pod_namespace, "default");
+        assert_eq!(req.pod_uid, "3b673e1d-289e-4210-8ceb-5a253b48d390");
+        assert_eq!(
+            req.root_dir,
+            "/run/containerd/io.containerd.runtime.v2.task/k8s.io/5da35096/rootfs"
+        );
+        assert_eq!(
+            req.cgroups_path,
+            "kubepods-besteffort-pod3b673e1d_289e_4210_8ceb_5a253b48d390.slice:cri-containerd:5da35096"
+        );
+
+        // Without annotations in the spec, the ones of the state are used
+        let request = hook_request(HookType::DeleteContainer, &state, &Spec::default());
+        let Some(Event::DeleteContainer(req)) = request.event else {
+            panic!("expected a delete container event");
+        };
+        assert_eq!(
+            req.annotations["io.kubernetes.cri.container-name"],
+            "from-state"
+        );
+        assert!(req.cgroups_path.is_empty());
+    }
+
+    #[test]
+    fn test_hook_type() {
+        assert_eq!(
+            HookType::parse("createRuntime"),
+            Some(HookType::CreateContainer)
+        );
+        assert_eq!(HookType::parse("poststart"), Some(HookType::StartContainer));
+        assert_eq!(HookType::parse("poststop"), Some(HookType::DeleteContainer));
+        assert_eq!(HookType::parse("prestop"), None);
+    }
+}
@@ -1,49 +1,187 @@
 use crate::api::{runtime_hook_request, RuntimeHookRequest};
-use crate::rthooks::{Callbacks, RtHookError};
+use crate::rthooks::{RtHookError, RuntimeHook};
+use crate::watcher::PodStore;
+use futures::future::{join_all, BoxFuture};
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::args::CreateContainerArg;
+use super::args::{ContainerArg, CreateContainerArg};
 
-#[derive(Default)]
+// The runtime waits for the hooks, so a stuck callback must not block it
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
 pub struct Runner {
-    pub callbacks: Vec<Callbacks>,
-    // TODO: watcher   watcher.PodAccessor
+    pub hooks: Vec<Arc<dyn RuntimeHook>>,
+    watcher: Option<PodStore>,
+    timeout: Duration,
 }
 
-impl Runner {
-    pub(crate) fn with_watcher() -> Self {
+// A runner without hooks nor watcher, both are set at init
+impl Default for Runner {
+    fn default() -> Self {
         Runner {
-            callbacks: vec![],
-            // watcher: watcher.PodAccessor::new(),
-        }
-    }
-    pub fn register_callback(&mut self, callback: Callbacks) {
-        self.callbacks.push(callback);
-    }
-
-    // TODO: call callbacks asynchronously
-    pub fn run_hooks(&self, request: &RuntimeHookRequest) -> Result<(), RtHookError> {
-        if let Some(runtime_hook_request::Event::CreateContainer(container)) = &request.event {
-            let mut args = CreateContainerArg::new(container.clone());
-            let errors: Vec<String> = self
-                .callbacks
-                .iter()
-                .filter_map(|callback| {
-                    let create_container = &callback.create_container;
-                    create_container(&mut args)
-                        .err()
-                        .map(|e| format!("create_container callback failed: {}", e))
-                })
-                .collect();
-
-            if errors.is_empty() {
-                Ok(())
-            } else {
-                Err(RtHookError::RunHooksError(errors.join("; ")))
+            hooks: vec![],
+            watcher: None,
+            timeout: CALLBACK_TIMEOUT,
+        }
+    }
+}
+
+impl Runner {
+    // The hooks run before the agent is fully started, so the pod store is
+    // set once the informer exists.
+    pub fn set_watcher(&mut self, watcher: PodStore) {
+        self.watcher = Some(watcher);
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn register_hook(&mut self, hook: Arc<dyn RuntimeHook>) {
+        self.hooks.push(hook);
+    }
+
+    // Runs the callback of every hook concurrently, each with its own timeout,
+    // and returns all the errors.
+    async fn run_callbacks<'a, F>(&'a self, callback: F) -> Result<(), RtHookError>
+    where
+        F: Fn(&'a dyn RuntimeHook) -> BoxFuture<'a, Result<(), RtHookError>>,
+    {
+        let results = join_all(self.hooks.iter().map(|hook| {
+            let fut = callback(hook.as_ref());
+            async move {
+                match tokio::time::timeout(self.timeout, fut).await {
+                    Ok(result) => result,
+                    Err(_) => Err(RtHookError::Timeout(hook.name().to_string(), self.timeout)),
+                }
             }
+        }))
+        .await;
+
+        let errors: Vec<RtHookError> = results.into_iter().filter_map(|r| r.err()).collect();
+        if errors.is_empty() {
+            Ok(())
         } else {
-            Err(RtHookError::UnsupportedEvent(
-                "Only CreateContainer event is supported".to_string(),
-            ))
+            Err(RtHookError::RunHooksError(errors))
+        }
+    }
+
+    pub async fn run_hooks(&self, request: &RuntimeHookRequest) -> Result<(), RtHookError> {
+        let Some(event) = &request.event else {
+            return Err(RtHookError::UnsupportedEvent(
+                "runtime hook request without event".to_string(),
+            ));
+        };
+
+        match event {
+            runtime_hook_request::Event::CreateContainer(req) => {
+                let arg = CreateContainerArg::new(req.clone(), self.watcher.clone());
+                self.run_callbacks(|hook| hook.create_container(&arg)).await
+            }
+            runtime_hook_request::Event::StartContainer(req) => {
+                let arg = ContainerArg::from(req.clone());
+                self.run_callbacks(|hook| hook.start_container(&arg)).await
+            }
+            runtime_hook_request::Event::StopContainer(req) => {
+                let arg = ContainerArg::from(req.clone());
+                self.run_callbacks(|hook| hook.stop_container(&arg)).await
+            }
+            runtime_hook_request::Event::DeleteContainer(req) => {
+                let arg = ContainerArg::from(req.clone());
+                self.run_callbacks(|hook| hook.delete_container(&arg)).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{CreateContainer, StopContainer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct TestHook {
+        name: String,
+        delay: Duration,
+        fail: bool,
+        calls: AtomicUsize,
+    }
+
+    impl TestHook {
+        fn new(name: &str, delay: Duration, fail: bool) -> Arc<Self> {
+            Arc::new(Self {
+                name: name.to_string(),
+                delay,
+                fail,
+                calls: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[tonic::async_trait]
+    impl RuntimeHook for TestHook {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn create_container(&self, _arg: &CreateContainerArg) -> Result<(), RtHookError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err(RtHookError::CreateContainerError(self.name.clone()));
+            }
+            Ok(())
         }
     }
+
+    fn create_request() -> RuntimeHookRequest {
+        RuntimeHookRequest {
+            event: Some(runtime_hook_request::Event::CreateContainer(
+                CreateContainer::default(),
+            )),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_hooks_concurrently() {
+        let mut runner = Runner::default();
+        let hooks: Vec<_> = (0..3)
+            .map(|i| TestHook::new(&format!("hook{}", i), Duration::from_millis(200), false))
+            .collect();
+        for hook in &hooks {
+            runner.register_hook(hook.clone());
+        }
+
+        let start = std::time::Instant::now();
+        runner.run_hooks(&create_request()).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(hooks.iter().all(|h| h.calls.load(Ordering::SeqCst) == 1));
+
+        // Events the hooks do not handle do nothing
+        let request = RuntimeHookRequest {
+            event: Some(runtime_hook_request::Event::StopContainer(
+                StopContainer::default(),
+            )),
+        };
+        runner.run_hooks(&request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_hooks_errors() {
+        let mut runner = Runner::default();
+        runner.set_timeout(Duration::from_millis(50));
+        runner.register_hook(TestHook::new("ok", Duration::ZERO, false));
+        runner.register_hook(TestHook::new("failing", Duration::ZERO, true));
+        runner.register_hook(TestHook::new("stuck", Duration::from_secs(10), false));
+
+        let Err(RtHookError::RunHooksError(errors)) = runner.run_hooks(&create_request()).await
+        else {
+            panic!("expected the errors of the hooks");
+        };
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], RtHookError::CreateContainerError(name) if name == "failing"));
+        assert!(matches!(&errors[1], RtHookError::Timeout(name, _) if name == "stuck"));
+    }
 }
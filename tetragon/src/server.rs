@@ -150,6 +150,7 @@ impl FineGuidanceSensors for FineGuidanceSensorsService {
     ) -> std::result::Result<Response<RuntimeHookResponse>, Status> {
         info!("runtime_hook: {:?}", request);
         rthooks::run_hooks(&request.into_inner())
+            .await
             .map_err(|e| Status::internal(format!("failed to run hooks: {}", e)))?;
         Ok(Response::new(RuntimeHookResponse {}))
     }
@@ -0,0 +1,196 @@
+use ahash::AHashMap;
+use k8s_openapi::api::core::v1::Pod;
+use kube::core::PartialObjectMeta;
+use kube::runtime::watcher;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+// Annotation of mirror pods, set by the kubelet to the hash of the static pod
+pub const CONFIG_MIRROR_ANNOTATION: &str = "kubernetes.io/config.mirror";
+
+#[derive(Debug, Default)]
+struct PodIndexMaps {
+    by_uid: AHashMap<String, Arc<Pod>>,
+    by_config_hash: AHashMap<String, Arc<Pod>>,
+}
+
+impl PodIndexMaps {
+    fn insert(&mut self, pod: Arc<Pod>) {
+        if let Some(hash) = config_hash(&pod) {
+            self.by_config_hash.insert(hash.to_string(), pod.clone());
+        }
+        if let Some(uid) = &pod.metadata.uid {
+            self.by_uid.insert(uid.clone(), pod);
+        }
+    }
+
+    fn remove(&mut self, pod: &Pod) {
+        if let Some(hash) = config_hash(pod) {
+            self.by_config_hash.remove(hash);
+        }
+        if let Some(uid) = &pod.metadata.uid {
+            self.by_uid.remove(uid);
+        }
+    }
+}
+
+pub(crate) fn config_hash(pod: &Pod) -> Option<&str> {
+    pod.metadata
+        .annotations
+        .as_ref()?
+        .get(CONFIG_MIRROR_ANNOTATION)
+        .map(String::as_str)
+}
+
+// Pods indexed by uid and, for mirror pods, by the hash of their static pod.
+// Unlike the container caches, it also has the pods whose containers are not
+// started yet, which is when the runtime hooks look them up.
+#[derive(Debug, Clone, Default)]
+pub struct PodIndex {
+    maps: Arc<RwLock<PodIndexMaps>>,
+    buffer: Arc<RwLock<PodIndexMaps>>,
+}
+
+impl PodIndex {
+    pub(crate) fn insert(&self, pod: Arc<Pod>) {
+        self.maps.write().insert(pod);
+    }
+
+    pub(crate) fn remove(&self, pod: &Pod) {
+        self.maps.write().remove(pod);
+    }
+
+    // The pods of a relist go to a buffer, swapped in when the list is done
+    pub(crate) fn init(&self) {
+        *self.buffer.write() = PodIndexMaps::default();
+    }
+
+    pub(crate) fn init_apply(&self, pod: Arc<Pod>) {
+        self.buffer.write().insert(pod);
+    }
+
+    pub(crate) fn init_done(&self) {
+        let buffer = std::mem::take(&mut *self.buffer.write());
+        *self.maps.write() = buffer;
+    }
+
+    // Applies an event of the metadata watcher of the cluster pods, keeping
+    // only the mirror pods.
+    pub(crate) fn apply_mirror_event(&self, event: &watcher::Event<PartialObjectMeta<Pod>>) {
+        let to_pod = |meta: &PartialObjectMeta<Pod>| {
+            Arc::new(Pod {
+                metadata: meta.metadata.clone(),
+                ..Default::default()
+            })
+        };
+        match event {
+            watcher::Event::Apply(meta) => {
+                let pod = to_pod(meta);
+                if config_hash(&pod).is_some() {
+                    self.insert(pod);
+                }
+            }
+            watcher::Event::Delete(meta) => self.remove(&to_pod(meta)),
+            watcher::Event::Init => self.init(),
+            watcher::Event::InitApply(meta) => {
+                let pod = to_pod(meta);
+                if config_hash(&pod).is_some() {
+                    self.init_apply(pod);
+                }
+            }
+            watcher::Event::InitDone => self.init_done(),
+        }
+    }
+
+    #[must_use]
+    pub fn find_pod(&self, uid: &str) -> Option<Arc<Pod>> {
+        self.maps.read().by_uid.get(uid).cloned()
+    }
+
+    #[must_use]
+    pub fn find_mirror_pod(&self, hash: &str) -> Option<Arc<Pod>> {
+        self.maps.read().by_config_hash.get(hash).cloned()
+    }
+
+    /// Return the number of pods in the index
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.maps.read().by_uid.len()
+    }
+
+    /// Return whether the index is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.maps.read().by_uid.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use kube::core::PartialObjectMetaExt;
+    use std::collections::BTreeMap;
+
+    fn pod(name: &str, uid: &str, mirror_hash: Option<&str>) -> Arc<Pod> {
+        Arc::new(Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                uid: Some(uid.to_string()),
+                annotations: mirror_hash.map(|hash| {
+                    BTreeMap::from([(CONFIG_MIRROR_ANNOTATION.to_string(), hash.to_string())])
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_pod_index() {
+        let index = PodIndex::default();
+        let nginx = pod("nginx", "uid-1", None);
+        let apiserver = pod("kube-apiserver-node1", "uid-2", Some("hash-2"));
+
+        index.init();
+        index.init_apply(nginx.clone());
+        assert!(index.find_pod("uid-1").is_none());
+        index.init_done();
+        index.insert(apiserver.clone());
+
+        assert_eq!(
+            index.find_pod("uid-1").unwrap().metadata.name,
+            nginx.metadata.name
+        );
+        assert_eq!(
+            index.find_mirror_pod("hash-2").unwrap().metadata.name,
+            apiserver.metadata.name
+        );
+        assert!(index.find_mirror_pod("uid-2").is_none());
+
+        index.remove(&apiserver);
+        assert!(index.find_pod("uid-2").is_none());
+        assert!(index.find_mirror_pod("hash-2").is_none());
+    }
+
+    #[test]
+    fn test_mirror_pod_events() {
+        let index = PodIndex::default();
+        let meta = |pod: Arc<Pod>| pod.metadata.clone().into_response_partial::<Pod>();
+        let nginx = meta(pod("nginx", "uid-1", None));
+        let apiserver = meta(pod("kube-apiserver-node2", "uid-2", Some("hash-2")));
+
+        index.apply_mirror_event(&watcher::Event::Init);
+        index.apply_mirror_event(&watcher::Event::InitApply(nginx.clone()));
+        index.apply_mirror_event(&watcher::Event::InitApply(apiserver.clone()));
+        index.apply_mirror_event(&watcher::Event::InitDone);
+
+        // Only mirror pods are kept
+        assert_eq!(index.len(), 1);
+        assert!(index.find_pod("uid-1").is_none());
+        assert!(index.find_mirror_pod("hash-2").is_some());
+
+        index.apply_mirror_event(&watcher::Event::Delete(apiserver));
+        assert!(index.is_empty());
+    }
+}
@@ -1,23 +1,92 @@
 use futures::{Stream, StreamExt};
 use k8s_openapi::api::core::v1::Pod;
-use kube::{api::Api, runtime::watcher, Client};
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use kube::{
+    api::Api,
+    runtime::{metadata_watcher, watcher},
+    Client,
+};
+use opentelemetry::metrics::ObservableGauge;
+use opentelemetry::{global, KeyValue};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Notify};
 use tracing::*;
 
 mod delayed_init;
+pub(crate) mod index;
 use ahash::AHashMap;
 use async_stream::stream;
 use delayed_init::DelayedInit;
-use parking_lot::RwLock;
+pub use index::PodIndex;
+use parking_lot::{Mutex, RwLock};
 use std::fmt::Debug;
 use thiserror::Error;
 
+use crate::cgroups::{linux::get_deployment_mode, DeploymentCode};
 use crate::podhelpers::extract_container_ids;
 
 type ContainerID = String;
 type PodCacheInner = Arc<RwLock<AHashMap<ContainerID, Arc<Pod>>>>;
 
+// Notifications of the containers looked up before their pod is in the caches
+#[derive(Debug, Default, Clone)]
+struct Waiters(Arc<Mutex<AHashMap<ContainerID, Arc<Notify>>>>);
+
+impl Waiters {
+    fn register(&self, key: &str) -> Arc<Notify> {
+        self.0.lock().entry(key.to_string()).or_default().clone()
+    }
+
+    // Drops the notification of the key once its last waiter is done
+    fn release(&self, key: &str, waiter: Arc<Notify>) {
+        let mut waiters = self.0.lock();
+        drop(waiter);
+        if waiters.get(key).is_some_and(|n| Arc::strong_count(n) == 1) {
+            waiters.remove(key);
+        }
+    }
+
+    fn wake(&self, key: &str) {
+        if let Some(notify) = self.0.lock().get(key) {
+            notify.notify_waiters();
+        }
+    }
+
+    fn wake_all(&self) {
+        for notify in self.0.lock().values() {
+            notify.notify_waiters();
+        }
+    }
+}
+
+// Name of the node of the agent, set from spec.nodeName with the downward API.
+// Only the pods of the node are watched when it is set.
+pub static NODE_NAME: LazyLock<Option<String>> =
+    LazyLock::new(|| std::env::var("NODE_NAME").ok().filter(|s| !s.is_empty()));
+
+// Cluster-wide cache of the mirror pods, for when they are not bound to
+// NODE_NAME, like when the kubelet registers the node under another name. Only
+// the metadata of the pods is watched, and only mirror pods are kept.
+static MIRROR_POD_CACHE: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("MIRROR_POD_CACHE")
+        .map(|s| s == "1")
+        .unwrap_or(false)
+});
+
+// Kubernetes integration, on with ENABLE_K8S_API=1 and off with
+// ENABLE_K8S_API=0. When not set, it is on only when the agent runs in a pod,
+// not because a kubeconfig lies around. Read after detect_deployment_mode.
+pub static ENABLE_K8S_API: LazyLock<bool> = LazyLock::new(|| {
+    match std::env::var("ENABLE_K8S_API").as_deref() {
+        Ok("1") => return true,
+        Ok("0") => return false,
+        _ => {}
+    }
+
+    get_deployment_mode() == DeploymentCode::Kubernetes
+        || std::env::var_os("KUBERNETES_SERVICE_HOST").is_some()
+});
+
 #[derive(Debug)]
 pub struct PodInformer {
     running_cache: PodCacheInner,
@@ -32,6 +101,13 @@ pub struct PodInformer {
 
     terminated_cache_max_size: usize,
 
+    index: PodIndex,
+    cluster_index: PodIndex,
+
+    waiters: Waiters,
+
+    started: Instant,
+
     event_sender: broadcast::Sender<watcher::Event<Pod>>,
 }
 
@@ -54,29 +130,68 @@ impl PodInformer {
             terminated_ready_tx: Some(terminated_ready_tx),
             terminated_ready_rx: Arc::new(terminated_ready_rx),
             terminated_cache_max_size: max_size,
+            index: PodIndex::default(),
+            cluster_index: PodIndex::default(),
+            waiters: Waiters::default(),
+            started: Instant::now(),
             event_sender,
         }
     }
 
-    pub async fn run(self, stop: impl std::future::Future<Output = ()>) -> anyhow::Result<()> {
+    pub async fn run(mut self, stop: impl std::future::Future<Output = ()>) -> anyhow::Result<()> {
+        if !*ENABLE_K8S_API {
+            // Nothing to watch, the caches stay empty so that nobody waits
+            // for pods
+            info!("Kubernetes API disabled, not watching pods");
+            self.apply_watcher_event(&watcher::Event::Init);
+            self.apply_watcher_event(&watcher::Event::InitDone);
+            stop.await;
+            return Ok(());
+        }
+
         info!("Starging PodInformer");
+        self.started = Instant::now();
         let client = Client::try_default().await?;
         let api = Api::<Pod>::all(client);
         let use_watchlist = std::env::var("WATCHLIST")
             .map(|s| s == "1")
             .unwrap_or(false);
-        let wc = if use_watchlist {
+        let mut wc = if use_watchlist {
             watcher::Config::default().streaming_lists()
         } else {
             watcher::Config::default()
         };
-        let stream = reflector(self, watcher(api, wc));
+        match NODE_NAME.as_deref() {
+            Some(node) => {
+                info!("Watching the pods of node {}", node);
+                wc = wc.fields(&format!("spec.nodeName={}", node));
+            }
+            None => warn!("NODE_NAME is not set, watching the pods of the whole cluster"),
+        }
+
+        let _cache_size = register_cache_metrics(&self);
+        let cluster_index = self.cluster_index.clone();
+        let stream = reflector(self, watcher(api.clone(), wc));
         let mut stream = Box::pin(stream);
 
+        // Without the cache, the stream stays pending
+        let mut mirror_stream = if *MIRROR_POD_CACHE && NODE_NAME.is_some() {
+            info!("Watching the mirror pods of the whole cluster");
+            metadata_watcher(api, watcher::Config::default())
+                .map(move |event| match event {
+                    Ok(event) => cluster_index.apply_mirror_event(&event),
+                    Err(e) => warn!("Mirror pod watcher error: {:?}", e),
+                })
+                .boxed()
+        } else {
+            futures::stream::pending::<()>().boxed()
+        };
+
         futures::pin_mut!(stop);
         loop {
             tokio::select! {
                 _ = stream.next() => {}
+                _ = mirror_stream.next() => {}
                 _ = &mut stop => {
                     info!("Stopping PodInformer");
                     break;
@@ -91,14 +206,26 @@ impl PodInformer {
         PodCache {
             store: self.running_cache.clone(),
             ready_rx: self.running_ready_rx.clone(),
+            waiters: self.waiters.clone(),
         }
     }
 
+    #[must_use]
+    pub fn as_index(&self) -> PodIndex {
+        self.index.clone()
+    }
+
+    #[must_use]
+    pub fn as_cluster_index(&self) -> PodIndex {
+        self.cluster_index.clone()
+    }
+
     #[must_use]
     pub fn as_terminated_cache(&self) -> PodCache {
         PodCache {
             store: self.terminated_cache.clone(),
             ready_rx: self.terminated_ready_rx.clone(),
+            waiters: self.waiters.clone(),
         }
     }
 
@@ -113,6 +240,7 @@ impl PodInformer {
         match event {
             watcher::Event::Apply(pod) => {
                 let pod = Arc::new(pod.clone());
+                self.index.insert(pod.clone());
 
                 let Some((running_ids, terminated_ids)) = extract_container_ids(&pod) else {
                     warn!("Pod has no container IDs, skipping: {:?}", pod);
@@ -121,7 +249,7 @@ impl PodInformer {
 
                 running_ids.iter().for_each(|id| {
                     debug!("Adding pod to running cache: {:?}", id);
-                    self.running_cache.write().insert(id.clone(), pod.clone());
+                    self.insert_to_running_cache(id.clone(), pod.clone());
                 });
 
                 terminated_ids.iter().for_each(|id| {
@@ -131,6 +259,7 @@ impl PodInformer {
             }
             watcher::Event::Delete(pod) => {
                 let pod = Arc::new(pod.clone());
+                self.index.remove(&pod);
 
                 let Some((running_ids, terminated_ids)) = extract_container_ids(&pod) else {
                     warn!("Pod has no container IDs, skipping: {:?}", pod);
@@ -138,7 +267,7 @@ impl PodInformer {
                 };
 
                 running_ids.iter().for_each(|id| {
-                    self.running_cache.write().insert(id.clone(), pod.clone());
+                    self.insert_to_running_cache(id.clone(), pod.clone());
                 });
 
                 terminated_ids.iter().for_each(|id| {
@@ -147,11 +276,13 @@ impl PodInformer {
                 });
             }
             watcher::Event::Init => {
+                self.index.init();
                 self.running_cache_buffer = AHashMap::new();
                 self.terminated_cache_buffer = AHashMap::new();
             }
             watcher::Event::InitApply(pod) => {
                 let pod = Arc::new(pod.clone());
+                self.index.init_apply(pod.clone());
 
                 let Some((running_ids, terminated_ids)) = extract_container_ids(&pod) else {
                     warn!("Pod has no container IDs, skipping: {:?}", pod);
@@ -167,6 +298,7 @@ impl PodInformer {
                 });
             }
             watcher::Event::InitDone => {
+                self.index.init_done();
                 let mut running_cache = self.running_cache.write();
 
                 // Swap the buffer into the store
@@ -184,20 +316,30 @@ impl PodInformer {
 
                 // Mark as ready after the Restart, "releasing" any calls to Store::wait_until_ready()
                 if let Some(running_ready_tx) = self.running_ready_tx.take() {
-                    running_ready_tx.init(())
+                    running_ready_tx.init(());
+                    record_initial_sync(self.started.elapsed());
                 }
                 if let Some(terminated_ready_tx) = self.terminated_ready_tx.take() {
                     terminated_ready_tx.init(())
                 }
+                drop(running_cache);
+                drop(terminated_cache);
+                self.waiters.wake_all();
             }
         }
         debug!("running pods: {:?}", self.running_cache.read().keys());
         debug!("terminated pods: {:?}", self.terminated_cache.read().keys());
     }
 
+    fn insert_to_running_cache(&self, key: ContainerID, value: Arc<Pod>) {
+        self.running_cache.write().insert(key.clone(), value);
+        self.waiters.wake(&key);
+    }
+
     fn insert_to_terminated_cache(&self, key: ContainerID, value: Arc<Pod>) {
         let mut cache = self.terminated_cache.write();
-        cache.insert(key, value);
+        cache.insert(key.clone(), value);
+        self.waiters.wake(&key);
 
         if cache.len() > self.terminated_cache_max_size {
             let excess = cache.len() - self.terminated_cache_max_size;
@@ -229,6 +371,7 @@ pub struct InformerDropped(delayed_init::InitDropped);
 pub struct PodCache {
     store: PodCacheInner,
     ready_rx: Arc<DelayedInit<()>>,
+    waiters: Waiters,
 }
 
 impl PodCache {
@@ -236,6 +379,35 @@ impl PodCache {
         self.ready_rx.get().await.map_err(InformerDropped)
     }
 
+    /// Wait until the cache is ready and has the key, for at most timeout
+    pub async fn wait_for(&self, key: &str, timeout: Duration) -> Option<Arc<Pod>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        // Before the initial list, the cache is empty rather than missing the key
+        if !matches!(
+            tokio::time::timeout_at(deadline, self.wait_until_ready()).await,
+            Ok(Ok(()))
+        ) {
+            return self.get(key);
+        }
+
+        let waiter = self.waiters.register(key);
+        let pod = loop {
+            // Registered before the lookup, so that an insert in between wakes it up
+            let notified = waiter.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if let Some(pod) = self.get(key) {
+                break Some(pod);
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                break self.get(key);
+            }
+        };
+        self.waiters.release(key, waiter);
+        pod
+    }
+
     #[must_use]
     pub fn get(&self, key: &str) -> Option<Arc<Pod>> {
         let store = self.store.read();
@@ -269,6 +441,8 @@ impl PodCache {
 pub struct PodStore {
     pub running: PodCache,
     pub terminated: PodCache,
+    pub index: PodIndex,
+    pub cluster_index: PodIndex,
 }
 
 impl PodStore {
@@ -279,34 +453,40 @@ impl PodStore {
         Ok(())
     }
 
+    /// Get a pod by its container ID from either running or terminated pods
     #[must_use]
-    pub fn get_with_retry(&self, container_id: &str, max_retries: usize) -> Option<Arc<Pod>> {
-        let mut attempts = 0;
-
-        loop {
-            if let Some(pod) = self
-                .running
-                .get(container_id)
-                .or_else(|| self.terminated.get(container_id))
-            {
-                return Some(pod);
-            }
-
-            attempts += 1;
-            if attempts >= max_retries {
-                break;
-            }
+    pub fn get(&self, container_id: &str) -> Option<Arc<Pod>> {
+        self.running
+            .get(container_id)
+            .or_else(|| self.terminated.get(container_id))
+    }
 
-            std::thread::sleep(std::time::Duration::from_millis(100));
+    /// Get a pod by its container ID, waiting for at most timeout until its
+    /// container is running
+    pub async fn wait_for(&self, container_id: &str, timeout: Duration) -> Option<Arc<Pod>> {
+        if let Some(pod) = self.get(container_id) {
+            return Some(pod);
         }
+        self.running
+            .wait_for(container_id, timeout)
+            .await
+            .or_else(|| self.terminated.get(container_id))
+    }
 
-        None
+    /// Get a pod by its uid, from the pods of the node or the cluster-wide cache
+    #[must_use]
+    pub fn find_pod(&self, uid: &str) -> Option<Arc<Pod>> {
+        self.index
+            .find_pod(uid)
+            .or_else(|| self.cluster_index.find_pod(uid))
     }
 
-    /// Get a pod by its container ID from either running or terminated pods
+    /// Get a mirror pod by the hash of its static pod
     #[must_use]
-    pub fn get(&self, container_id: &str) -> Option<Arc<Pod>> {
-        self.get_with_retry(container_id, 1)
+    pub fn find_mirror_pod(&self, hash: &str) -> Option<Arc<Pod>> {
+        self.index
+            .find_mirror_pod(hash)
+            .or_else(|| self.cluster_index.find_mirror_pod(hash))
     }
 }
 
@@ -318,11 +498,46 @@ pub fn pod_informer() -> (PodStore, PodInformer) {
         PodStore {
             running: i.as_running_cache(),
             terminated: i.as_terminated_cache(),
+            index: i.as_index(),
+            cluster_index: i.as_cluster_index(),
         },
         i,
     )
 }
 
+// Sizes of the pod caches, observed when the metrics are exported
+fn register_cache_metrics(informer: &PodInformer) -> ObservableGauge<u64> {
+    let running = informer.running_cache.clone();
+    let terminated = informer.terminated_cache.clone();
+    let index = informer.index.clone();
+    let cluster_index = informer.cluster_index.clone();
+    global::meter("tetragon")
+        .u64_observable_gauge("pod_cache_size")
+        .with_description("Number of entries in the pod caches")
+        .with_callback(move |observer| {
+            let sizes = [
+                ("running", running.read().len()),
+                ("terminated", terminated.read().len()),
+                ("index", index.len()),
+                ("cluster_index", cluster_index.len()),
+            ];
+            for (cache, size) in sizes {
+                observer.observe(size as u64, &[KeyValue::new("cache", cache)]);
+            }
+        })
+        .build()
+}
+
+fn record_initial_sync(elapsed: std::time::Duration) {
+    info!("PodInformer synced in {:?}", elapsed);
+    global::meter("tetragon")
+        .f64_gauge("pod_informer_initial_sync_seconds")
+        .with_description("Time until the initial list of pods is synced")
+        .with_unit("s")
+        .build()
+        .record(elapsed.as_secs_f64(), &[]);
+}
+
 pub fn reflector<W>(mut informer: PodInformer, stream: W) -> impl Stream<Item = W::Item>
 where
     W: Stream<Item = watcher::Result<watcher::Event<Pod>>>,
@@ -340,3 +555,63 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{
+        ContainerState, ContainerStateRunning, ContainerStatus, PodStatus,
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn running_pod(name: &str, container_id: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                uid: Some(format!("uid-{}", name)),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                container_statuses: Some(vec![ContainerStatus {
+                    container_id: Some(format!("containerd://{}", container_id)),
+                    state: Some(ContainerState {
+                        running: Some(ContainerStateRunning::default()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for() {
+        let (store, mut informer) = pod_informer();
+
+        // Waits for the initial list, then for the pod of the container
+        let waiter = tokio::spawn({
+            let store = store.clone();
+            async move { store.wait_for("c1", Duration::from_secs(5)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        informer.apply_watcher_event(&watcher::Event::Init);
+        informer.apply_watcher_event(&watcher::Event::InitDone);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        informer.apply_watcher_event(&watcher::Event::Apply(running_pod("nginx", "c1")));
+        let pod = waiter.await.unwrap().unwrap();
+        assert_eq!(pod.metadata.name.as_deref(), Some("nginx"));
+        assert!(store.running.waiters.0.lock().is_empty());
+
+        let start = Instant::now();
+        assert!(store
+            .wait_for("unknown", Duration::from_millis(50))
+            .await
+            .is_none());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(store.running.waiters.0.lock().is_empty());
+    }
+}